@@ -0,0 +1,298 @@
+use crate::prelude::*;
+
+use std::fmt::{Display, Formatter};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::SystemTime;
+
+const FILE_NAME: &str = "access_lists.txt";
+
+const FILE_HEADER: &str = "\
+#Who may join this server. One entry per line, the file is reloaded when it changes.
+#  ban-ip <ip or cidr>      ban-user <username>
+#  allow-ip <ip or cidr>    allow-user <username>
+#  allowlist on|off         (when on, only allowed IPs or usernames may join)
+#  admin-ip <ip or cidr>    (may run admin commands, usernames are not verified so admins are listed by IP only)
+";
+
+//Single address or a whole network in CIDR notation, like '192.168.0.0/16':
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IpRange {
+	address: IpAddr,
+	prefix_length: u8,
+}
+
+impl IpRange {
+	pub fn contains(&self, address: &IpAddr) -> bool {
+		//The server socket is IPv6, IPv4 clients show up as IPv4-mapped addresses:
+		match (self.address, address.to_canonical()) {
+			(IpAddr::V4(network), IpAddr::V4(address)) => {
+				IpRange::matches_prefix(u32::from(network) as u128, u32::from(address) as u128, 32, self.prefix_length)
+			}
+			(IpAddr::V6(network), IpAddr::V6(address)) => {
+				IpRange::matches_prefix(u128::from(network), u128::from(address), 128, self.prefix_length)
+			}
+			_ => false,
+		}
+	}
+	
+	fn matches_prefix(network: u128, address: u128, bits: u8, prefix_length: u8) -> bool {
+		let ignored_bits = (bits - prefix_length) as u32;
+		network.checked_shr(ignored_bits).unwrap_or(0) == address.checked_shr(ignored_bits).unwrap_or(0)
+	}
+}
+
+impl FromStr for IpRange {
+	type Err = String;
+	
+	fn from_str(text: &str) -> Result<Self, Self::Err> {
+		let (address, prefix_length) = match text.split_once('/') {
+			Some((address, prefix_length)) => (address, Some(prefix_length)),
+			None => (text, None),
+		};
+		let address = IpAddr::from_str(address)
+			.map_err(|_| format!("'{}' is not an IP address", address))?
+			.to_canonical();
+		let max_prefix_length = if address.is_ipv4() { 32 } else { 128 };
+		let prefix_length = match prefix_length {
+			Some(prefix_length) => match u8::from_str(prefix_length) {
+				Ok(prefix_length) if prefix_length <= max_prefix_length => prefix_length,
+				_ => return Err(format!("'{}' is not a prefix length between 0 and {}", prefix_length, max_prefix_length)),
+			},
+			None => max_prefix_length,
+		};
+		Ok(IpRange {
+			address,
+			prefix_length,
+		})
+	}
+}
+
+impl Display for IpRange {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		let max_prefix_length = if self.address.is_ipv4() { 32 } else { 128 };
+		if self.prefix_length == max_prefix_length {
+			write!(f, "{}", self.address)
+		} else {
+			write!(f, "{}/{}", self.address, self.prefix_length)
+		}
+	}
+}
+
+#[derive(Default)]
+struct Lists {
+	banned_ips: Vec<IpRange>,
+	banned_users: Vec<String>,
+	allowed_ips: Vec<IpRange>,
+	allowed_users: Vec<String>,
+	allowlist_enabled: bool,
+	admin_ips: Vec<IpRange>,
+}
+
+//Ban list and allowlist, checked when a client wants to connect.
+//Stored in the data folder, so that they apply to every world. Usernames are compared case-insensitively.
+pub struct AccessLists {
+	path: PathBuf,
+	lists: Lists,
+	//Modification time of the file, when it was last loaded or saved. Used to notice edits while running:
+	loaded_modification: Option<SystemTime>,
+}
+
+impl AccessLists {
+	pub fn load(data_folder: &Path) -> EhResult<AccessLists> {
+		let mut access_lists = AccessLists {
+			path: data_folder.join(FILE_NAME),
+			lists: Lists::default(),
+			loaded_modification: None,
+		};
+		if access_lists.path.exists() {
+			access_lists.reload().wrap(ex!("While loading access lists"))?;
+		}
+		Ok(access_lists)
+	}
+	
+	fn modification_time(&self) -> Option<SystemTime> {
+		std::fs::metadata(&self.path).and_then(|metadata| metadata.modified()).ok()
+	}
+	
+	fn reload(&mut self) -> EhResult<()> {
+		let modification = self.modification_time();
+		let content = unwrap_or_else_return!(std::fs::read_to_string(&self.path), |error| {
+			exception!("Failed to read ", self.path.to_string_lossy(), ": ", format!("{:?}", error))
+		});
+		self.lists = AccessLists::parse(&content)?;
+		self.loaded_modification = modification;
+		log_info!("Loaded access lists: ", self.lists.banned_ips.len() + self.lists.banned_users.len(), " bans, ", self.lists.allowed_ips.len() + self.lists.allowed_users.len(), " allowlist entries, allowlist is ", if self.lists.allowlist_enabled { "on" } else { "off" });
+		Ok(())
+	}
+	
+	//Picks up changes made to the file while the server is running. A broken file keeps the previous lists:
+	fn reload_if_changed(&mut self) {
+		let modification = self.modification_time();
+		if modification.is_none() || modification == self.loaded_modification {
+			return;
+		}
+		if let Err(error) = self.reload() {
+			error.print();
+			log_warn!("Keeping the previous access lists.");
+			self.loaded_modification = modification; //Do not complain again, until the file is edited again.
+		}
+	}
+	
+	fn parse(content: &str) -> EhResult<Lists> {
+		let mut lists = Lists::default();
+		for (index, line) in content.lines().enumerate() {
+			let line = line.trim();
+			if line.is_empty() || line.starts_with('#') {
+				continue;
+			}
+			let (keyword, value) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+			let value = value.trim();
+			if value.is_empty() {
+				return exception!("Line ", index + 1, " '", line, "' has no value");
+			}
+			let parse_range = || match IpRange::from_str(value) {
+				Ok(range) => Ok(range),
+				Err(error) => exception!("Line ", index + 1, ": ", error),
+			};
+			match keyword {
+				"ban-ip" => lists.banned_ips.push(parse_range()?),
+				"allow-ip" => lists.allowed_ips.push(parse_range()?),
+				"ban-user" => lists.banned_users.push(value.to_lowercase()),
+				"allow-user" => lists.allowed_users.push(value.to_lowercase()),
+				"admin-ip" => lists.admin_ips.push(parse_range()?),
+				//Anyone may join with any username, admin rights cannot depend on it:
+				"admin-user" => log_warn!("Line ", index + 1, ": 'admin-user' is not supported, admins can only be listed with 'admin-ip'. Ignoring it."),
+				"allowlist" => lists.allowlist_enabled = match value {
+					"on" => true,
+					"off" => false,
+					_ => return exception!("Line ", index + 1, " expected 'on' or 'off' for the allowlist, got '", value, "'"),
+				},
+				_ => return exception!("Line ", index + 1, " starts with unknown keyword '", keyword, "'"),
+			}
+		}
+		Ok(lists)
+	}
+	
+	fn save(&mut self) -> EhResult<()> {
+		let mut content = String::from(FILE_HEADER);
+		content.push_str(&format!("allowlist {}\n", if self.lists.allowlist_enabled { "on" } else { "off" }));
+		for range in &self.lists.banned_ips {
+			content.push_str(&format!("ban-ip {}\n", range));
+		}
+		for username in &self.lists.banned_users {
+			content.push_str(&format!("ban-user {}\n", username));
+		}
+		for range in &self.lists.allowed_ips {
+			content.push_str(&format!("allow-ip {}\n", range));
+		}
+		for username in &self.lists.allowed_users {
+			content.push_str(&format!("allow-user {}\n", username));
+		}
+		for range in &self.lists.admin_ips {
+			content.push_str(&format!("admin-ip {}\n", range));
+		}
+		//Write to a temporary file first, a crash while writing would else lose all bans:
+		let temporary_path = self.path.with_extension("tmp");
+		unwrap_or_else_return!(std::fs::write(&temporary_path, content), |error| {
+			exception!("Failed to write ", temporary_path.to_string_lossy(), ": ", format!("{:?}", error))
+		});
+		unwrap_or_else_return!(std::fs::rename(&temporary_path, &self.path), |error| {
+			exception!("Failed to replace ", self.path.to_string_lossy(), ": ", format!("{:?}", error))
+		});
+		self.loaded_modification = self.modification_time();
+		Ok(())
+	}
+	
+	//Returns the reason to show the client, if it may not join:
+	pub fn check(&mut self, address: &IpAddr, username: &str) -> Option<String> {
+		self.reload_if_changed();
+		let username = username.to_lowercase();
+		let lists = &self.lists;
+		if lists.banned_users.contains(&username) || lists.banned_ips.iter().any(|range| range.contains(address)) {
+			return Some(String::from("You are banned from this server."));
+		}
+		if lists.allowlist_enabled
+			&& !lists.allowed_users.contains(&username)
+			&& !lists.allowed_ips.iter().any(|range| range.contains(address)) {
+			return Some(String::from("You are not on the allowlist of this server."));
+		}
+		None
+	}
+	
+	pub fn is_admin(&mut self, address: &IpAddr) -> bool {
+		self.reload_if_changed();
+		self.lists.admin_ips.iter().any(|range| range.contains(address))
+	}
+	
+	//Changes made while running, each one is saved right away.
+	//They return false, if there was nothing to change:
+	
+	pub fn ban_ip(&mut self, range: IpRange) -> EhResult<bool> {
+		self.reload_if_changed();
+		self.edit(|lists| AccessLists::add(&mut lists.banned_ips, range))
+	}
+	
+	pub fn unban_ip(&mut self, range: &IpRange) -> EhResult<bool> {
+		self.reload_if_changed();
+		self.edit(|lists| AccessLists::remove(&mut lists.banned_ips, range))
+	}
+	
+	pub fn ban_user(&mut self, username: &str) -> EhResult<bool> {
+		self.reload_if_changed();
+		self.edit(|lists| AccessLists::add(&mut lists.banned_users, username.to_lowercase()))
+	}
+	
+	pub fn unban_user(&mut self, username: &str) -> EhResult<bool> {
+		self.reload_if_changed();
+		self.edit(|lists| AccessLists::remove(&mut lists.banned_users, &username.to_lowercase()))
+	}
+	
+	pub fn allow_ip(&mut self, range: IpRange) -> EhResult<bool> {
+		self.reload_if_changed();
+		self.edit(|lists| AccessLists::add(&mut lists.allowed_ips, range))
+	}
+	
+	pub fn disallow_ip(&mut self, range: &IpRange) -> EhResult<bool> {
+		self.reload_if_changed();
+		self.edit(|lists| AccessLists::remove(&mut lists.allowed_ips, range))
+	}
+	
+	pub fn allow_user(&mut self, username: &str) -> EhResult<bool> {
+		self.reload_if_changed();
+		self.edit(|lists| AccessLists::add(&mut lists.allowed_users, username.to_lowercase()))
+	}
+	
+	pub fn disallow_user(&mut self, username: &str) -> EhResult<bool> {
+		self.reload_if_changed();
+		self.edit(|lists| AccessLists::remove(&mut lists.allowed_users, &username.to_lowercase()))
+	}
+	
+	pub fn set_allowlist_enabled(&mut self, enabled: bool) -> EhResult<bool> {
+		self.reload_if_changed();
+		self.edit(|lists| enabled != std::mem::replace(&mut lists.allowlist_enabled, enabled))
+	}
+	
+	fn edit<F: FnOnce(&mut Lists) -> bool>(&mut self, change: F) -> EhResult<bool> {
+		if !change(&mut self.lists) {
+			return Ok(false);
+		}
+		self.save().wrap(ex!("While saving access lists"))?;
+		Ok(true)
+	}
+	
+	fn add<T: PartialEq>(list: &mut Vec<T>, entry: T) -> bool {
+		if list.contains(&entry) {
+			return false;
+		}
+		list.push(entry);
+		true
+	}
+	
+	fn remove<T: PartialEq>(list: &mut Vec<T>, entry: &T) -> bool {
+		let length = list.len();
+		list.retain(|existing| existing != entry);
+		list.len() != length
+	}
+}
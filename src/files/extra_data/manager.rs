@@ -6,8 +6,9 @@ use std::net::SocketAddr;
 use crate::files::extra_data::entries::{flag_list_order, simulation_paused, simulation_speed, world_type_data};
 use crate::files::extra_data::entries::display_configuration::DisplayConfiguration;
 use crate::files::extra_data::entries::display_configurations_order::DisplayConfigurationsOrder;
-use crate::lidgren::lidgren_server::ServerInstance;
+use crate::lidgren::network_thread::NetworkThread;
 use crate::network::message_pack::pretty_printer::pretty_print_data;
+use crate::network::message_pack::writer as mp_writer;
 use crate::network::packets::c2s::extra_data_change::ExtraDataChange;
 use crate::network::packets::c2s::extra_data_request::ExtraDataRequest;
 use crate::network::packets::s2c::extra_data_update::ExtraDataUpdate;
@@ -76,7 +77,7 @@ impl ExtraDataManager {
 		Ok(instance)
 	}
 	
-	pub fn handle_request(&mut self, request_packet: ExtraDataRequest, server: &mut ServerInstance, address: SocketAddr) {
+	pub fn handle_request(&mut self, request_packet: ExtraDataRequest, network: &NetworkThread, address: SocketAddr) {
 		pretty_print_data(&mut CustomIterator::borrow(&request_packet.default));
 		let extra_data = unwrap_or_else_return!(self.resolve_key(&request_packet.key[..]), || {
 			log_warn!("Client tried to query unknown ExtraData: '", request_packet.key, "'");
@@ -90,10 +91,10 @@ impl ExtraDataManager {
 		let packet = Self::pack(extra_data);
 		let mut buffer = Vec::new();
 		packet.write(&mut buffer);
-		server.send_to(address, buffer);
+		network.send_to(address, buffer);
 	}
 	
-	pub fn handle_change(&mut self, change_packet: ExtraDataChange, server: &mut ServerInstance, address: SocketAddr) {
+	pub fn handle_change(&mut self, change_packet: ExtraDataChange, network: &NetworkThread, address: SocketAddr) {
 		pretty_print_data(&mut CustomIterator::borrow(&change_packet.data_bytes));
 		let extra_data = unwrap_or_else_return!(self.resolve_key(&change_packet.key[..]), || {
 			log_warn!("Client tried to update unknown ExtraData: '", change_packet.key, "'");
@@ -111,7 +112,20 @@ impl ExtraDataManager {
 		let packet = Self::pack(extra_data);
 		let mut buffer = Vec::new();
 		packet.write(&mut buffer);
-		server.send_to(address, buffer);
+		network.send_to(address, buffer);
+	}
+	
+	//Changes the simulation speed on behalf of the server, returns the update packet for the clients:
+	pub fn set_simulation_speed(&mut self, speed: f64) -> Option<Vec<u8>> {
+		let mut bytes = Vec::new();
+		mp_writer::write_float_64(&mut bytes, speed);
+		let extra_data = self.resolve_key(simulation_speed::KEY)?;
+		if !extra_data.update_bytes_if_valid(&bytes) {
+			return None;
+		}
+		let mut buffer = Vec::new();
+		Self::pack(extra_data).write(&mut buffer);
+		Some(buffer)
 	}
 	
 	fn validate_request_data(request_packet: &ExtraDataRequest, extra_data: &dyn GenericExtraData) -> bool {
@@ -2,19 +2,23 @@ use crate::prelude::*;
 
 use std::collections::HashMap;
 
-use crate::files::world_data::world_structs::{Component, ComponentAddress, PegAddress, Wire, World};
+use crate::files::world_data::world_structs::{Component, ComponentAddress, PegAddress, Version, Wire, WireAddress, World};
 use crate::files::world_files::WorldFolderAccess;
 use crate::util::custom_iterator::CustomIterator;
 
-const LW_FILE_HEADER: &str = "Logic World save";
-const LW_FILE_FOOTER: &str = "redstone sux lol";
+pub const LW_FILE_HEADER: &str = "Logic World save";
+pub const LW_FILE_FOOTER: &str = "redstone sux lol";
 const LW_FILE_HEADER_BYTES: &[u8] = LW_FILE_HEADER.as_bytes();
 const LW_FILE_FOOTER_BYTES: &[u8] = LW_FILE_FOOTER.as_bytes();
 
 pub fn load_world(folders: &WorldFolderAccess) -> EhResult<World> {
 	let data_vec = folders.load_world_file().wrap(ex!("While loading world"))?;
-	let iterator = &mut CustomIterator::borrow(&data_vec[..]);
-	return read_from_file(iterator);
+	parse_world(&data_vec[..])
+}
+
+pub fn parse_world(data: &[u8]) -> EhResult<World> {
+	let iterator = &mut CustomIterator::borrow(data);
+	read_from_file(iterator)
 }
 
 fn read_from_file(iterator: &mut CustomIterator) -> EhResult<World> {
@@ -60,7 +64,7 @@ fn read_from_file(iterator: &mut CustomIterator) -> EhResult<World> {
 	if iterator.remaining() < header_data_length {
 		return exception!("File not large enough to hold the basic save information. Needs at least ", header_data_length, " but only got ", iterator.remaining());
 	}
-	let _game_version = read_version_unchecked(iterator);
+	let game_version = read_version_unchecked(iterator);
 	{
 		let save_type = iterator.next_unchecked();
 		match save_type {
@@ -74,12 +78,13 @@ fn read_from_file(iterator: &mut CustomIterator) -> EhResult<World> {
 	let amount_wires = iterator.read_le_u32().unwrap();
 	
 	let amount_mods = read_semi_unsigned_int(iterator).wrap(ex!("While reading amount of mods in save file"))?;
-	let mut mods = HashMap::with_capacity(amount_mods as usize);
+	//Kept in file order, so that saving writes them back the same way:
+	let mut mods = Vec::with_capacity(amount_mods as usize);
 	for _ in 0..amount_mods {
 		let mod_name = read_string(iterator).wrap(ex!("While reading mod name"))?;
 		let mod_version = read_version(iterator).wrap(ex!("While reading mod version"))?;
 		log_debug!("Found mod entry '", mod_name, "' with version ", format!("{}.{}.{}.{}", mod_version.0, mod_version.1, mod_version.2, mod_version.3));
-		mods.insert(mod_name, mod_version);
+		mods.push((mod_name, mod_version));
 	}
 	
 	let components_map_count = read_semi_unsigned_int(iterator).wrap(ex!("While reading amount of component dictionary entries"))?;
@@ -123,10 +128,10 @@ fn read_from_file(iterator: &mut CustomIterator) -> EhResult<World> {
 		if amount_custom_data_bytes < -1 {
 			return exception!("Expected -1 or higher for component custom data byte amount, got: ", amount_custom_data_bytes);
 		}
-		let custom_data = if amount_custom_data_bytes > 0 {
-			iterator.read_bytes(amount_custom_data_bytes as usize).wrap(ex!("While reading component custom data bytes"))?
+		let custom_data = if amount_custom_data_bytes >= 0 {
+			Some(iterator.read_bytes(amount_custom_data_bytes as usize).wrap(ex!("While reading component custom data bytes"))?)
 		} else {
-			Vec::with_capacity(0)
+			None
 		};
 		components.push(Component {
 			address: component_address,
@@ -144,7 +149,7 @@ fn read_from_file(iterator: &mut CustomIterator) -> EhResult<World> {
 	
 	let mut wires = Vec::with_capacity(amount_wires as usize);
 	let bytes_per_wire = 9 + 9 + 4 + 4;
-	for _ in 0..amount_wires {
+	for index in 0..amount_wires {
 		if iterator.remaining() < bytes_per_wire {
 			return exception!("Ran out of bytes while reading wire entry, safe file seems corrupted. Remaining bytes: ", iterator.remaining(), " / ", bytes_per_wire);
 		}
@@ -153,6 +158,9 @@ fn read_from_file(iterator: &mut CustomIterator) -> EhResult<World> {
 		let circuit_state_id = read_semi_unsigned_int(iterator).wrap(ex!("While reading a wires circuit state id"))?;
 		let wire_rotation = iterator.read_le_f32().unwrap(); //Bound check is done above.
 		wires.push(Wire {
+			address: WireAddress {
+				id: index + 1,
+			},
 			peg_a: peg_address_a,
 			peg_b: peg_address_b,
 			circuit_state_id,
@@ -165,14 +173,10 @@ fn read_from_file(iterator: &mut CustomIterator) -> EhResult<World> {
 	let amount_of_bytes = read_semi_unsigned_int(iterator).wrap(ex!("While reading amount of circuit state bytes"))?;
 	let mut circuit_states = Vec::with_capacity(amount_of_bytes as usize * 8);
 	for byte in iterator.read_bytes(amount_of_bytes as usize).wrap(ex!("While reading circuit state bytes"))? {
-		circuit_states.push(byte & 0b00000001 != 0);
-		circuit_states.push(byte & 0b0000001 != 0);
-		circuit_states.push(byte & 0b000001 != 0);
-		circuit_states.push(byte & 0b00001 != 0);
-		circuit_states.push(byte & 0b0001 != 0);
-		circuit_states.push(byte & 0b001 != 0);
-		circuit_states.push(byte & 0b01 != 0);
-		circuit_states.push(byte & 0b1 != 0);
+		//Lowest bit first:
+		for bit in 0..8 {
+			circuit_states.push(byte & (1 << bit) != 0);
+		}
 	}
 	
 	if iterator.remaining() != LW_FILE_FOOTER_BYTES.len() {
@@ -181,11 +185,18 @@ fn read_from_file(iterator: &mut CustomIterator) -> EhResult<World> {
 	
 	log_debug!("Finished reading the world file.");
 	
+	let next_component_id = components.iter().map(|component| component.address.id).max().unwrap_or(0).saturating_add(1);
+	let next_wire_id = amount_wires.saturating_add(1);
 	Ok(World {
+		file_version,
+		game_version,
+		mods,
 		component_id_map: component_dictionary,
 		components,
 		wires,
 		circuit_states,
+		next_component_id,
+		next_wire_id,
 	})
 }
 
@@ -266,13 +277,13 @@ fn read_string(iterator: &mut CustomIterator) -> EhResult<String> {
 	String::from_utf8(iterator.read_bytes(amount_bytes as usize).wrap(ex!("While reading string bytes"))?).map_ex(ex!("While validating string bytes as string"))
 }
 
-fn read_version(iterator: &mut CustomIterator) -> EhResult<(i32, i32, i32, i32)> {
+fn read_version(iterator: &mut CustomIterator) -> EhResult<Version> {
 	if iterator.remaining() < 16 {
 		return exception!("Ran out of bytes, while parsing version: ", iterator.remaining(), "/", 16);
 	}
 	Ok(read_version_unchecked(iterator))
 }
 
-fn read_version_unchecked(iterator: &mut CustomIterator) -> (i32, i32, i32, i32) {
+fn read_version_unchecked(iterator: &mut CustomIterator) -> Version {
 	(iterator.read_le_i32().unwrap(), iterator.read_le_i32().unwrap(), iterator.read_le_i32().unwrap(), iterator.read_le_i32().unwrap())
 }
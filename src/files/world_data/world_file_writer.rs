@@ -0,0 +1,224 @@
+use crate::prelude::*;
+
+use crate::files::world_data::world_file_parser::{LW_FILE_FOOTER, LW_FILE_HEADER};
+use crate::files::world_data::world_structs::{ComponentAddress, PegAddress, Version, World};
+use crate::files::world_files::WorldFolderAccess;
+
+//Newest version the parser understands, positions are stored as fixed point:
+const LW_FILE_VERSION: u8 = 6;
+const SAVE_TYPE_WORLD: u8 = 1;
+
+pub fn save_world(folders: &WorldFolderAccess, world: &World) -> EhResult<()> {
+	let bytes = write_to_file(world)?;
+	folders.save_world_file(&bytes).wrap(ex!("While saving world"))
+}
+
+//Counterpart of the world file parser:
+fn write_to_file(world: &World) -> EhResult<Vec<u8>> {
+	//Older formats get converted while loading, writing them as the newest one would silently upgrade the save:
+	if world.file_version != LW_FILE_VERSION {
+		return exception!("Cannot save world loaded from file version ", world.file_version, ", only version ", LW_FILE_VERSION, " can be written.");
+	}
+	
+	let mut buffer = Vec::new();
+	buffer.extend_from_slice(LW_FILE_HEADER.as_bytes());
+	
+	//### SAVE INFO: ###########
+	
+	buffer.push(LW_FILE_VERSION);
+	write_version(&mut buffer, &world.game_version);
+	buffer.push(SAVE_TYPE_WORLD);
+	buffer.extend_from_slice(&(world.components.len() as u32).to_le_bytes());
+	buffer.extend_from_slice(&(world.wires.len() as u32).to_le_bytes());
+	
+	write_semi_unsigned_int(&mut buffer, world.mods.len() as u32);
+	for (mod_name, mod_version) in world.mods.iter() {
+		write_string(&mut buffer, mod_name);
+		write_version(&mut buffer, mod_version);
+	}
+	
+	write_semi_unsigned_int(&mut buffer, world.component_id_map.len() as u32);
+	for (index, identifier) in world.component_id_map.iter() {
+		buffer.extend_from_slice(&index.to_le_bytes());
+		write_string(&mut buffer, identifier);
+	}
+	
+	//### COMPONENTS: ###########
+	
+	for component in world.components.iter() {
+		write_component_address(&mut buffer, &component.address);
+		write_component_address(&mut buffer, &component.parent);
+		buffer.extend_from_slice(&component.type_id.to_le_bytes());
+		buffer.extend_from_slice(&component.relative_position.0.to_le_bytes());
+		buffer.extend_from_slice(&component.relative_position.1.to_le_bytes());
+		buffer.extend_from_slice(&component.relative_position.2.to_le_bytes());
+		buffer.extend_from_slice(&component.relative_alignment.0.to_le_bytes());
+		buffer.extend_from_slice(&component.relative_alignment.1.to_le_bytes());
+		buffer.extend_from_slice(&component.relative_alignment.2.to_le_bytes());
+		buffer.extend_from_slice(&component.relative_alignment.3.to_le_bytes());
+		write_semi_unsigned_int(&mut buffer, component.inputs.len() as u32);
+		for circuit_state_id in component.inputs.iter() {
+			write_semi_unsigned_int(&mut buffer, *circuit_state_id);
+		}
+		write_semi_unsigned_int(&mut buffer, component.outputs.len() as u32);
+		for circuit_state_id in component.outputs.iter() {
+			write_semi_unsigned_int(&mut buffer, *circuit_state_id);
+		}
+		match &component.custom_data {
+			Some(custom_data) => {
+				buffer.extend_from_slice(&(custom_data.len() as i32).to_le_bytes());
+				buffer.extend_from_slice(custom_data);
+			}
+			None => buffer.extend_from_slice(&(-1i32).to_le_bytes()),
+		}
+	}
+	
+	//### WIRES: ################
+	
+	for wire in world.wires.iter() {
+		write_peg_address(&mut buffer, &wire.peg_a);
+		write_peg_address(&mut buffer, &wire.peg_b);
+		write_semi_unsigned_int(&mut buffer, wire.circuit_state_id);
+		buffer.extend_from_slice(&wire.rotation.to_le_bytes());
+	}
+	
+	//### CIRCUIT STATES: #######
+	
+	let circuit_state_bytes: Vec<u8> = world.circuit_states.chunks(8).map(|states| {
+		//Lowest bit first:
+		states.iter().enumerate().fold(0u8, |byte, (bit, state)| byte | ((*state as u8) << bit))
+	}).collect();
+	write_semi_unsigned_int(&mut buffer, circuit_state_bytes.len() as u32);
+	buffer.extend_from_slice(&circuit_state_bytes);
+	
+	buffer.extend_from_slice(LW_FILE_FOOTER.as_bytes());
+	Ok(buffer)
+}
+
+fn write_peg_address(buffer: &mut Vec<u8>, peg_address: &PegAddress) {
+	buffer.push(peg_address.is_input as u8);
+	write_component_address(buffer, &peg_address.component_address);
+	write_semi_unsigned_int(buffer, peg_address.peg_index);
+}
+
+fn write_component_address(buffer: &mut Vec<u8>, component_address: &ComponentAddress) {
+	buffer.extend_from_slice(&component_address.id.to_le_bytes());
+}
+
+fn write_semi_unsigned_int(buffer: &mut Vec<u8>, value: u32) {
+	buffer.extend_from_slice(&(value as i32).to_le_bytes());
+}
+
+fn write_string(buffer: &mut Vec<u8>, value: &str) {
+	buffer.extend_from_slice(&(value.len() as u32).to_le_bytes());
+	buffer.extend_from_slice(value.as_bytes());
+}
+
+fn write_version(buffer: &mut Vec<u8>, version: &Version) {
+	buffer.extend_from_slice(&version.0.to_le_bytes());
+	buffer.extend_from_slice(&version.1.to_le_bytes());
+	buffer.extend_from_slice(&version.2.to_le_bytes());
+	buffer.extend_from_slice(&version.3.to_le_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	use std::collections::HashMap;
+	
+	use crate::files::world_data::world_file_parser::parse_world;
+	use crate::files::world_data::world_structs::{Component, Wire, WireAddress};
+	
+	//Save with nothing but circuit states, written by hand after the file format:
+	fn save_with_circuit_states(state_bytes: &[u8]) -> Vec<u8> {
+		let mut save = Vec::new();
+		save.extend_from_slice(LW_FILE_HEADER.as_bytes());
+		save.push(LW_FILE_VERSION);
+		write_version(&mut save, &(0, 91, 0, 0));
+		save.push(SAVE_TYPE_WORLD);
+		save.extend_from_slice(&0u32.to_le_bytes()); //Components
+		save.extend_from_slice(&0u32.to_le_bytes()); //Wires
+		save.extend_from_slice(&0i32.to_le_bytes()); //Mods
+		save.extend_from_slice(&0i32.to_le_bytes()); //Component-ID map
+		save.extend_from_slice(&(state_bytes.len() as i32).to_le_bytes());
+		save.extend_from_slice(state_bytes);
+		save.extend_from_slice(LW_FILE_FOOTER.as_bytes());
+		save
+	}
+	
+	#[test]
+	fn circuit_states_are_read_lowest_bit_first() {
+		let world = parse_world(&save_with_circuit_states(&[0b0000_0101, 0b1000_0000])).unwrap();
+		let mut expected = vec![false; 16];
+		expected[0] = true;
+		expected[2] = true;
+		expected[15] = true;
+		assert_eq!(world.circuit_states, expected);
+	}
+	
+	#[test]
+	fn written_world_loads_the_same() {
+		let component = |id, parent, inputs: Vec<u32>, outputs: Vec<u32>| Component {
+			address: ComponentAddress { id },
+			parent: ComponentAddress { id: parent },
+			type_id: if inputs.is_empty() { 1 } else { 2 },
+			relative_position: (id as i32 * 300, 150, -(id as i32)),
+			relative_alignment: (0.0, 0.70710677, 0.0, 0.70710677),
+			inputs,
+			outputs,
+			//Empty and missing custom data have to stay apart:
+			custom_data: match id {
+				1 => Some(vec![1, 2, 3]),
+				2 => Some(Vec::new()),
+				_ => None,
+			},
+		};
+		let peg = |id, is_input| PegAddress { is_input, component_address: ComponentAddress { id }, peg_index: 0 };
+		let world = World {
+			file_version: LW_FILE_VERSION,
+			//Mods are written in the order they got loaded:
+			game_version: (0, 91, 2, 1234),
+			mods: vec![(String::from("ZMod"), (1, 0, 0, 0)), (String::from("AMod"), (2, 1, 0, 0))],
+			component_id_map: HashMap::from([(1, String::from("MHG.CircuitBoard")), (2, String::from("MHG.Inverter"))]),
+			components: vec![component(1, 0, vec![], vec![]), component(2, 1, vec![0], vec![1]), component(5, 1, vec![2], vec![3])],
+			wires: vec![Wire {
+				address: WireAddress { id: 1 },
+				peg_a: peg(2, false),
+				peg_b: peg(5, true),
+				circuit_state_id: 1,
+				rotation: 1.5,
+			}],
+			circuit_states: (0..16).map(|index| index % 3 == 1).collect(),
+			next_component_id: 6,
+			next_wire_id: 2,
+		};
+		let loaded = parse_world(&write_to_file(&world).unwrap()).unwrap();
+		assert!(loaded == world);
+	}
+	
+	#[test]
+	fn older_file_versions_are_not_saved() {
+		let mut save = save_with_circuit_states(&[]);
+		save[LW_FILE_HEADER.len()] = 5;
+		let world = parse_world(&save).unwrap();
+		assert_eq!(world.file_version, 5);
+		assert!(write_to_file(&world).is_err());
+	}
+	
+	//Real saves are not part of the repository. Set LOGIC_WORLD_SAVE to the path of a 'data.logicworld' file
+	// and run with '--ignored' to check one:
+	#[test]
+	#[ignore = "needs LOGIC_WORLD_SAVE"]
+	fn real_save_round_trip() {
+		let path = std::env::var("LOGIC_WORLD_SAVE").expect("LOGIC_WORLD_SAVE has to be the path of a world file");
+		let original = std::fs::read(&path).unwrap();
+		let world = parse_world(&original).unwrap();
+		let written = write_to_file(&world).unwrap();
+		
+		//Circuit states are the last section, their bytes have to come out exactly as they went in:
+		let state_section_length = 4 + world.circuit_states.len() / 8 + LW_FILE_FOOTER.len();
+		assert_eq!(written[written.len() - state_section_length..], original[original.len() - state_section_length..]);
+		assert!(parse_world(&written).unwrap() == world);
+	}
+}
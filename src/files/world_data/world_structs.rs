@@ -1,12 +1,75 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
+pub type Version = (i32, i32, i32, i32);
+
+#[derive(PartialEq)]
 pub struct World {
+	//File format version the world got loaded from, only worlds in the written format can be saved again:
+	pub file_version: u8,
+	//Game version and mods that the world got saved with:
+	pub game_version: Version,
+	pub mods: Vec<(String, Version)>,
 	pub component_id_map: HashMap<u16, String>,
 	pub components: Vec<Component>,
 	pub wires: Vec<Wire>,
 	pub circuit_states: Vec<bool>,
+	//Next addresses to hand out. Only ever increase, so that requests for deleted objects cannot hit new ones:
+	pub next_component_id: u32,
+	pub next_wire_id: u32,
+}
+
+impl World {
+	pub fn allocate_component_address(&mut self) -> ComponentAddress {
+		let address = ComponentAddress {
+			id: self.next_component_id,
+		};
+		self.next_component_id += 1;
+		address
+	}
+	
+	pub fn allocate_wire_address(&mut self) -> WireAddress {
+		let address = WireAddress {
+			id: self.next_wire_id,
+		};
+		self.next_wire_id += 1;
+		address
+	}
+	
+	pub fn find_component(&self, address: &ComponentAddress) -> Option<&Component> {
+		self.components.iter().find(|component| component.address == *address)
+	}
+	
+	pub fn find_component_mut(&mut self, address: &ComponentAddress) -> Option<&mut Component> {
+		self.components.iter_mut().find(|component| component.address == *address)
+	}
+	
+	pub fn find_wire(&self, address: &WireAddress) -> Option<&Wire> {
+		self.wires.iter().find(|wire| wire.address == *address)
+	}
+	
+	//Circuit state id of a peg, if the component has that peg:
+	pub fn find_peg_state(&self, peg: &PegAddress) -> Option<u32> {
+		let component = self.find_component(&peg.component_address)?;
+		let pegs = if peg.is_input { &component.inputs } else { &component.outputs };
+		pegs.get(peg.peg_index as usize).copied()
+	}
+	
+	//The component and all components below it:
+	pub fn collect_subtree(&self, address: &ComponentAddress) -> HashSet<ComponentAddress> {
+		let mut subtree = HashSet::from([*address]);
+		let mut to_visit = vec![*address];
+		while let Some(parent) = to_visit.pop() {
+			for component in self.components.iter().filter(|component| component.parent == parent) {
+				if subtree.insert(component.address) {
+					to_visit.push(component.address);
+				}
+			}
+		}
+		subtree
+	}
 }
 
+#[derive(Clone, PartialEq)]
 pub struct Component {
 	pub address: ComponentAddress,
 	pub parent: ComponentAddress,
@@ -15,29 +78,45 @@ pub struct Component {
 	pub relative_alignment: (f32, f32, f32, f32),
 	pub inputs: Vec<u32>,
 	pub outputs: Vec<u32>,
-	pub custom_data: Vec<u8>,
+	//Missing custom data is not the same as empty custom data, saves keep them apart:
+	pub custom_data: Option<Vec<u8>>,
 }
 
+#[derive(Clone, PartialEq)]
 pub struct Wire {
+	//Save files do not store wire addresses, they are handed out when loading:
+	pub address: WireAddress,
 	pub peg_a: PegAddress,
 	pub peg_b: PegAddress,
 	pub circuit_state_id: u32,
 	pub rotation: f32,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct PegAddress {
 	pub is_input: bool,
 	pub component_address: ComponentAddress,
 	pub peg_index: u32,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct ComponentAddress {
 	pub id: u32,
 }
 
+impl ComponentAddress {
+	//Parent of components placed directly in the world:
+	pub const ROOT: ComponentAddress = ComponentAddress { id: 0 };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WireAddress {
+	pub id: u32,
+}
+
 //Not directly world, but part of CustomData and (probably) more:
 
-#[derive(Default)]
+#[derive(Default, Clone, Copy)]
 pub struct Color24 {
 	pub r: u8,
 	pub g: u8,
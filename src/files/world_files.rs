@@ -6,6 +6,8 @@ use std::fs;
 use crate::util::succ::succ_parser;
 
 pub struct WorldFolderAccess {
+	//Server wide files, the world folder is inside:
+	pub data_folder: PathBuf,
 	world_folder: PathBuf,
 	world_file: PathBuf,
 	pub extra_data_folder: PathBuf,
@@ -27,7 +29,7 @@ impl WorldFolderAccess {
 		let data_folder = current_dir.join(Path::new("data"));
 		if !data_folder.exists() {
 			log_warn!("Data directory does not exist, creating it!");
-			unwrap_or_else_return!(std::fs::create_dir(data_folder), |error| {
+			unwrap_or_else_return!(std::fs::create_dir(&data_folder), |error| {
 				exception!("Failed to create data directory: ", format!("{:?}", error))
 			});
 			return exception!("As the data directory was just created and this server can't create worlds yet. You have to copy a world into the ", "data", " folder. Make sure it is called '", "World", "'!");
@@ -67,6 +69,7 @@ impl WorldFolderAccess {
 		}
 		
 		Ok(Self {
+			data_folder,
 			world_folder,
 			world_file,
 			extra_data_folder,
@@ -79,6 +82,19 @@ impl WorldFolderAccess {
 		Ok(data_vec)
 	}
 	
+	//Writes a temporary file first and replaces the world file with it, a crash while writing won't destroy the world:
+	pub fn save_world_file(&self, bytes: &[u8]) -> EhResult<()> {
+		let temporary_file = self.world_file.with_extension("logicworld.tmp");
+		unwrap_or_else_return!(std::fs::write(&temporary_file, bytes), |error| {
+			exception!("Failed to write ", temporary_file.to_string_lossy(), ": ", format!("{:?}", error))
+		});
+		unwrap_or_else_return!(std::fs::rename(&temporary_file, &self.world_file), |error| {
+			exception!("Failed to replace ", self.world_file.to_string_lossy(), ": ", format!("{:?}", error))
+		});
+		log_debug!("Wrote world with ", bytes.len(), " bytes");
+		Ok(())
+	}
+	
 	pub fn load_file(path: &PathBuf) -> EhResult<Vec<u8>> {
 		let data_vec = unwrap_or_else_return!(std::fs::read(path), |error| {
 			exception!("Failed to read ", path.to_string_lossy(), ": ", format!("{:?}", error))
@@ -1,6 +1,7 @@
 pub mod files {
 	pub mod world_data {
 		pub mod world_file_parser;
+		pub mod world_file_writer;
 		pub mod world_structs;
 	}
 	pub mod extra_data {
@@ -15,6 +16,7 @@ pub mod files {
 		}
 	}
 	pub mod world_files;
+	pub mod access_lists;
 }
 
 pub mod network {
@@ -33,37 +35,74 @@ pub mod network {
 			pub mod discovery_request;
 			pub mod connection_established;
 			pub mod connection_approval;
+			pub mod client_loaded_world;
+			pub mod chat_message_sent;
 			pub mod player_position;
 			pub mod extra_data_request;
 			pub mod extra_data_change;
+			pub mod run_command;
+			pub mod building_request;
 		}
 		
 		pub mod s2c {
 			pub mod discovery_response;
 			pub mod world_initialization_packet;
 			pub mod extra_data_update;
+			pub mod chat_message_broadcast;
+			pub mod debug_message;
+			pub mod build_action_receipt;
+			pub mod world_update;
 		}
 	}
 }
 
+pub mod server {
+	pub mod player_sessions;
+	pub mod chat;
+	pub mod game_state;
+	pub mod commands;
+	pub mod building;
+	pub mod build_validation;
+}
+
 pub mod lidgren {
 	pub mod util {
 		pub mod formatter;
 	}
 	
 	pub mod channel_handler {
+		pub mod unreliable;
+		pub mod sequenced;
+		pub mod reliable_unordered;
+		pub mod reliable_sequenced;
 		pub mod reliable_ordered;
 	}
 	
 	pub mod channel_sender {
+		pub mod unreliable;
 		pub mod reliable_ordered;
 	}
 	
 	pub mod data_structures;
+	pub mod datagram_builder;
 	pub mod message_type;
 	pub mod lidgren_server;
 	pub mod connected_client;
 	pub mod data_types;
+	pub mod round_trip_time;
+	pub mod mtu_discovery;
+	pub mod transport;
+	pub mod network_simulator;
+	pub mod capture;
+	pub mod connection_statistics;
+	pub mod network_thread;
+	pub mod send_priority;
+	pub mod bandwidth_budget;
+	pub mod congestion_window;
+	pub mod flood_protection;
+	pub mod handshake;
+	#[cfg(test)]
+	pub mod test_client;
 }
 
 pub mod util {
@@ -0,0 +1,50 @@
+use std::time::{Duration, Instant};
+
+//The budget may be saved up for this long, so that sending can continue after a short pause with a burst:
+const BURST_DURATION: f32 = 0.05;
+
+//Limits how many bytes per second are sent to a client (token bucket).
+//Sending may overshoot the budget by one message, the debt is paid off before the next message is sent.
+pub struct BandwidthBudget {
+	bytes_per_second: Option<f32>,
+	//Bytes that may be sent right now, negative while in debt:
+	allowance: f32,
+	last_refill: Instant,
+}
+
+impl BandwidthBudget {
+	//No limit, if 'bytes_per_second' is None:
+	pub fn new(bytes_per_second: Option<usize>) -> Self {
+		Self {
+			bytes_per_second: bytes_per_second.map(|bytes_per_second| bytes_per_second as f32),
+			allowance: 0.0,
+			last_refill: Instant::now(),
+		}
+	}
+	
+	//Bytes that may be sent now, None if unlimited. The burst is at least a full datagram, else nothing could be sent:
+	pub fn available(&mut self, mtu: usize) -> Option<usize> {
+		let bytes_per_second = self.bytes_per_second?;
+		let now = Instant::now();
+		let burst = (bytes_per_second * BURST_DURATION).max(mtu as f32);
+		self.allowance = (self.allowance + (now - self.last_refill).as_secs_f32() * bytes_per_second).min(burst);
+		self.last_refill = now;
+		Some(self.allowance.max(0.0) as usize)
+	}
+	
+	pub fn spend(&mut self, bytes: usize) {
+		if self.bytes_per_second.is_some() {
+			self.allowance -= bytes as f32;
+		}
+	}
+	
+	//When there is enough budget for a full datagram again, None if there is already:
+	pub fn next_available_time(&self, mtu: usize) -> Option<Instant> {
+		let bytes_per_second = self.bytes_per_second?;
+		let missing = mtu as f32 - self.allowance;
+		if missing <= 0.0 {
+			return None;
+		}
+		Some(self.last_refill + Duration::from_secs_f32(missing / bytes_per_second))
+	}
+}
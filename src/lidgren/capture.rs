@@ -0,0 +1,355 @@
+use crate::prelude::*;
+
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::Write;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::lidgren::data_structures::{MESSAGE_HEADER_LENGTH, MessageHeader};
+use crate::lidgren::message_type::MessageType;
+use crate::lidgren::transport::{Transport, WakeupSignal, Waker};
+use crate::network::message_pack::reader as mp_reader;
+use crate::util::custom_iterator::CustomIterator;
+
+//Capture file layout (all numbers little endian):
+// Header: "LWCAP" + version byte
+// Per datagram: micros since start (u64), direction (u8), IP version (u8, 4 or 6), IP bytes, port (u16), length (u32), data
+const CAPTURE_MAGIC: &[u8] = b"LWCAP";
+const CAPTURE_VERSION: u8 = 1;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Direction {
+	Incoming,
+	Outgoing,
+}
+
+pub struct CapturedDatagram {
+	pub time: Duration,
+	pub direction: Direction,
+	pub address: SocketAddr,
+	pub data: Vec<u8>,
+}
+
+impl CapturedDatagram {
+	fn write(&self, buffer: &mut Vec<u8>) {
+		buffer.extend_from_slice(&(self.time.as_micros() as u64).to_le_bytes());
+		buffer.push(match self.direction {
+			Direction::Incoming => 0,
+			Direction::Outgoing => 1,
+		});
+		match self.address.ip() {
+			IpAddr::V4(ip) => {
+				buffer.push(4);
+				buffer.extend_from_slice(&ip.octets());
+			}
+			IpAddr::V6(ip) => {
+				buffer.push(6);
+				buffer.extend_from_slice(&ip.octets());
+			}
+		}
+		buffer.extend_from_slice(&self.address.port().to_le_bytes());
+		buffer.extend_from_slice(&(self.data.len() as u32).to_le_bytes());
+		buffer.extend_from_slice(&self.data);
+	}
+	
+	fn read(iterator: &mut CustomIterator) -> EhResult<CapturedDatagram> {
+		let time = Duration::from_micros(iterator.read_le_u64().wrap(ex!("While reading timestamp"))?);
+		let direction = match iterator.next().wrap(ex!("While reading direction"))? {
+			0 => Direction::Incoming,
+			1 => Direction::Outgoing,
+			other => return exception!("Unknown datagram direction ", other),
+		};
+		let ip = match iterator.next().wrap(ex!("While reading IP version"))? {
+			4 => {
+				let bytes: [u8; 4] = iterator.read_bytes(4).wrap(ex!("While reading IPv4 address"))?.try_into().unwrap();
+				IpAddr::V4(Ipv4Addr::from(bytes))
+			}
+			6 => {
+				let bytes: [u8; 16] = iterator.read_bytes(16).wrap(ex!("While reading IPv6 address"))?.try_into().unwrap();
+				IpAddr::V6(Ipv6Addr::from(bytes))
+			}
+			other => return exception!("Unknown IP version ", other),
+		};
+		let port = iterator.read_le_u16().wrap(ex!("While reading port"))?;
+		let length = iterator.read_le_u32().wrap(ex!("While reading datagram length"))?;
+		let data = iterator.read_bytes(length as usize).wrap(ex!("While reading datagram of ", length, " bytes"))?;
+		Ok(CapturedDatagram {
+			time,
+			direction,
+			address: SocketAddr::new(ip, port),
+			data,
+		})
+	}
+}
+
+pub fn read_capture(path: &Path) -> EhResult<Vec<CapturedDatagram>> {
+	let data = unwrap_or_else_return!(std::fs::read(path), |error| {
+		exception!("Failed to read capture file ", path.to_string_lossy(), ": ", format!("{:?}", error))
+	});
+	let mut iterator = CustomIterator::own(data);
+	let magic = iterator.read_bytes(CAPTURE_MAGIC.len()).wrap(ex!("While reading capture header"))?;
+	if magic != CAPTURE_MAGIC {
+		return exception!("File ", path.to_string_lossy(), " is not a capture file");
+	}
+	let version = iterator.next().wrap(ex!("While reading capture version"))?;
+	if version != CAPTURE_VERSION {
+		return exception!("Unsupported capture version ", version, " expected ", CAPTURE_VERSION);
+	}
+	let mut datagrams = Vec::new();
+	while iterator.has_more() {
+		let datagram = CapturedDatagram::read(&mut iterator).wrap(ex!("While reading datagram ", datagrams.len()))?;
+		datagrams.push(datagram);
+	}
+	Ok(datagrams)
+}
+
+// ### Recording: ###
+
+//Writes every datagram passing through the wrapped transport into a capture file.
+//Each datagram is written right away, as the server usually gets stopped by killing it.
+pub struct RecordingTransport<T: Transport> {
+	inner: T,
+	file: RefCell<File>,
+	start: Instant,
+}
+
+impl<T: Transport> RecordingTransport<T> {
+	pub fn create(inner: T, path: &Path) -> EhResult<RecordingTransport<T>> {
+		let mut file = unwrap_or_else_return!(File::create(path), |error| {
+			exception!("Failed to create capture file ", path.to_string_lossy(), ": ", format!("{:?}", error))
+		});
+		let mut header = CAPTURE_MAGIC.to_vec();
+		header.push(CAPTURE_VERSION);
+		file.write_all(&header).map_ex(ex!("While writing capture header"))?;
+		Ok(RecordingTransport {
+			inner,
+			file: RefCell::new(file),
+			start: Instant::now(),
+		})
+	}
+	
+	fn record(&self, direction: Direction, address: SocketAddr, data: &[u8]) {
+		let mut buffer = Vec::with_capacity(data.len() + 35);
+		CapturedDatagram {
+			time: self.start.elapsed(),
+			direction,
+			address,
+			data: data.to_vec(),
+		}.write(&mut buffer);
+		if let Err(error) = self.file.borrow_mut().write_all(&buffer) {
+			log_error!("Failed to write datagram to capture file: ", format!("{:?}", error));
+		}
+	}
+}
+
+impl<T: Transport> Transport for RecordingTransport<T> {
+	fn send(&self, data: &[u8], address: &SocketAddr) {
+		self.record(Direction::Outgoing, *address, data);
+		self.inner.send(data, address);
+	}
+	
+	fn receive(&mut self, input_buffer: &mut [u8]) -> Option<(usize, SocketAddr)> {
+		let (amount, address) = self.inner.receive(input_buffer)?;
+		self.record(Direction::Incoming, address, &input_buffer[..amount]);
+		Some((amount, address))
+	}
+	
+	fn wait_readable(&mut self, timeout: Option<Duration>) {
+		self.inner.wait_readable(timeout);
+	}
+	
+	fn waker(&self) -> Waker {
+		self.inner.waker()
+	}
+}
+
+// ### Replay: ###
+
+//How many recorded messages a sent message may skip, to still count as match. Timing differs on replay,
+// so resends can show up at other places.
+const REPLAY_SEARCH_WINDOW: usize = 16;
+
+struct ReplayState {
+	incoming: VecDeque<CapturedDatagram>,
+	//Recorded messages per remote, that the server is expected to send again:
+	expected_outgoing: HashMap<SocketAddr, VecDeque<Vec<u8>>>,
+	start: Option<Instant>,
+	matched: u32,
+	missing: u32,
+	unexpected: u32,
+}
+
+//Feeds a recorded session into the server and compares what it sends with what got recorded.
+//Only the content of messages is compared. Messages, that depend on timing (pings, acknowledges, MTU probes, connect responses),
+// as well as how messages got packed into datagrams, are ignored.
+//Every message passing through gets logged decoded, so that the session can be followed.
+#[derive(Clone)]
+pub struct ReplaySession {
+	state: Arc<Mutex<ReplayState>>,
+	signal: Arc<WakeupSignal>,
+}
+
+impl ReplaySession {
+	pub fn load(path: &Path) -> EhResult<ReplaySession> {
+		let datagrams = read_capture(path).wrap(ex!("While loading replay"))?;
+		let mut incoming = VecDeque::new();
+		let mut expected_outgoing: HashMap<SocketAddr, VecDeque<Vec<u8>>> = HashMap::new();
+		for datagram in datagrams {
+			match datagram.direction {
+				Direction::Incoming => incoming.push_back(datagram),
+				Direction::Outgoing => expected_outgoing.entry(datagram.address).or_default().extend(split_comparable_messages(&datagram.data)),
+			}
+		}
+		log_info!("Loaded capture with ", incoming.len(), " incoming datagrams");
+		Ok(ReplaySession {
+			state: Arc::new(Mutex::new(ReplayState {
+				incoming,
+				expected_outgoing,
+				start: None,
+				matched: 0,
+				missing: 0,
+				unexpected: 0,
+			})),
+			signal: Arc::new(WakeupSignal::default()),
+		})
+	}
+	
+	pub fn transport(&self) -> ReplayTransport {
+		ReplayTransport {
+			session: self.clone(),
+		}
+	}
+	
+	//True once every recorded incoming datagram got handed to the server:
+	pub fn is_finished(&self) -> bool {
+		self.state.lock().unwrap().incoming.is_empty()
+	}
+	
+	//Logs the summary, returns true if the server sent exactly what got recorded:
+	pub fn report(&self) -> bool {
+		let state = self.state.lock().unwrap();
+		let never_sent = state.expected_outgoing.values().map(|messages| messages.len() as u32).sum::<u32>();
+		log_info!("Replay finished: ", state.matched, " messages matched, ", state.missing, " recorded messages skipped, ", state.unexpected, " unexpected messages, ", never_sent, " recorded messages never sent.");
+		state.missing == 0 && state.unexpected == 0 && never_sent == 0
+	}
+}
+
+//Splits a datagram into its Lidgren messages, leaving out the ones that depend on timing:
+fn split_comparable_messages(data: &[u8]) -> Vec<Vec<u8>> {
+	split_messages(data).into_iter().filter_map(|(header, message)| match header.message_type {
+		MessageType::Ping
+		| MessageType::Pong
+		| MessageType::Acknowledge
+		| MessageType::ExpandMTURequest
+		| MessageType::ExpandMTUSuccess => None,
+		//Contains the random unique id and the uptime of the server. It is also resent until the remote answers,
+		// so how often it shows up depends on timing too:
+		MessageType::ConnectResponse => None,
+		_ => Some(message),
+	}).collect()
+}
+
+fn split_messages(data: &[u8]) -> Vec<(MessageHeader, Vec<u8>)> {
+	let mut messages = Vec::new();
+	let mut iterator = CustomIterator::borrow(data);
+	while iterator.remaining() >= MESSAGE_HEADER_LENGTH {
+		let start = iterator.pointer_save();
+		let Ok(header) = MessageHeader::from_stream(&mut iterator) else {
+			break;
+		};
+		if iterator.remaining() < header.bytes as usize {
+			break;
+		}
+		iterator.pointer_restore(start);
+		let message = iterator.read_slice_unchecked(MESSAGE_HEADER_LENGTH + header.bytes as usize).to_vec();
+		messages.push((header, message));
+	}
+	messages
+}
+
+//Logs each message of a datagram with its Lidgren header. User messages also show the Logic World packet id,
+// unless they are a fragment, which only the first one would contain:
+fn log_decoded(direction: &str, address: &SocketAddr, data: &[u8]) {
+	for (header, message) in split_messages(data) {
+		let mut description = format!("{:?} #{} ({} bytes)", header.message_type, header.sequence_number, header.bytes);
+		if header.fragment {
+			description.push_str(" fragment");
+		} else if !MessageType::is_system(&header.message_type) {
+			let mut iterator = CustomIterator::borrow(&message[MESSAGE_HEADER_LENGTH..]);
+			if let Ok(packet_id) = mp_reader::read_u32(&mut iterator) {
+				description.push_str(&format!(" packet id {}", packet_id));
+			}
+		}
+		log_info!("[Replay] ", direction, " ", address, ": ", description);
+	}
+}
+
+pub struct ReplayTransport {
+	session: ReplaySession,
+}
+
+impl Transport for ReplayTransport {
+	fn send(&self, data: &[u8], address: &SocketAddr) {
+		log_decoded("Sent to", address, data);
+		let mut state = self.session.state.lock().unwrap();
+		let state = &mut *state;
+		let expected = state.expected_outgoing.entry(*address).or_default();
+		for message in split_comparable_messages(data) {
+			match expected.iter().take(REPLAY_SEARCH_WINDOW).position(|recorded| *recorded == message) {
+				Some(index) => {
+					for skipped in expected.drain(..index) {
+						log_warn!("[Replay] Divergence: Recorded message to ", address, " was not sent: ", format!("{:x?}", skipped));
+						state.missing += 1;
+					}
+					expected.pop_front();
+					state.matched += 1;
+				}
+				None => {
+					log_warn!("[Replay] Divergence: Sent message to ", address, " which was not recorded: ", format!("{:x?}", message));
+					state.unexpected += 1;
+				}
+			}
+		}
+	}
+	
+	//Hands out the recorded datagrams with their original timing, relative to the first receive call:
+	fn receive(&mut self, input_buffer: &mut [u8]) -> Option<(usize, SocketAddr)> {
+		let mut state = self.session.state.lock().unwrap();
+		let elapsed = state.start.get_or_insert_with(Instant::now).elapsed();
+		if state.incoming.front()?.time > elapsed {
+			return None;
+		}
+		let datagram = state.incoming.pop_front().unwrap();
+		log_decoded("Received from", &datagram.address, &datagram.data);
+		let amount = datagram.data.len().min(input_buffer.len());
+		input_buffer[..amount].copy_from_slice(&datagram.data[..amount]);
+		Some((amount, datagram.address))
+	}
+	
+	fn wait_readable(&mut self, timeout: Option<Duration>) {
+		let next_datagram = {
+			let state = self.session.state.lock().unwrap();
+			let Some(start) = state.start else {
+				return; //Nothing received yet, so the replay did not start.
+			};
+			state.incoming.front().map(|datagram| (start + datagram.time).saturating_duration_since(Instant::now()))
+		};
+		let timeout = match (timeout, next_datagram) {
+			(Some(timeout), Some(next_datagram)) => Some(timeout.min(next_datagram)),
+			(timeout, next_datagram) => timeout.or(next_datagram),
+		};
+		if timeout.is_some_and(|timeout| timeout.is_zero()) {
+			return;
+		}
+		self.session.signal.wait(timeout);
+	}
+	
+	fn waker(&self) -> Waker {
+		let signal = self.session.signal.clone();
+		Arc::new(move || signal.wake())
+	}
+}
@@ -1,10 +1,10 @@
 use crate::prelude::*;
 
-use crate::lidgren::data_structures::MessageHeader;
+use crate::lidgren::data_structures::{MessageHeader, SEQUENCE_NUMBERS};
 use crate::util::custom_iterator::CustomIterator;
+use crate::lidgren::connection_statistics::ConnectionStatistics;
 
 const WINDOW_SIZE: usize = 64;
-const SEQUENCE_NUMBERS: usize = 1024;
 
 struct InternalMessage {
 	header: MessageHeader,
@@ -32,34 +32,34 @@ impl ReliableOrderedHandler {
 		header: MessageHeader,
 		mut message_data_iterator: CustomIterator,
 		output_list: &mut Vec<(MessageHeader, Vec<u8>)>,
-	) {
-		let relative_sequence_number = (header.sequence_number as i16 - self.latest_sequence_index as i16 + 1024 + 512) % 1024 - 512;
+		statistics: &mut ConnectionStatistics,
+	) -> bool {
+		let relative_sequence_number = (header.sequence_number as i32 - self.latest_sequence_index as i32 + 1024 + 512) % 1024 - 512;
 		log_debug!("Latest: ", self.latest_sequence_index, " Current: ", header.sequence_number, " Relative: ", relative_sequence_number);
 		
 		if relative_sequence_number < 0 {
-			//TODO: Acknowledge
 			//Received message is older, than what is already processed, so lets just discard it.
+			//It still has to be acknowledged (again), the previous acknowledge probably got lost.
 			log_debug!("Drop packet as it is too old: ", relative_sequence_number);
-			return;
+			statistics.duplicate_messages += 1;
+			return true;
 		}
 		
 		let data = message_data_iterator.read_bytes(message_data_iterator.remaining()).unwrap();
 		
 		if relative_sequence_number == 0 {
-			//TODO: Acknowledge
-			
 			//Assert: self.cycle_buffer[self.latest_sequence_index as usize % WINDOW_SIZE] is equal to None!
 			
 			//We had been waiting for you, enter!
 			output_list.push((header, data));
-			self.latest_sequence_index = (self.latest_sequence_index + 1) % SEQUENCE_NUMBERS as u16;
+			self.latest_sequence_index = (self.latest_sequence_index + 1) % SEQUENCE_NUMBERS;
 			
 			while let Some(buffered_message) = self.cycle_buffer[self.latest_sequence_index as usize % WINDOW_SIZE].take() {
 				output_list.push((buffered_message.header, buffered_message.data));
-				self.latest_sequence_index = (self.latest_sequence_index + 1) % SEQUENCE_NUMBERS as u16;
+				self.latest_sequence_index = (self.latest_sequence_index + 1) % SEQUENCE_NUMBERS;
 			}
 			
-			return;
+			return true;
 		}
 		
 		if relative_sequence_number > 64 {
@@ -70,20 +70,23 @@ impl ReliableOrderedHandler {
 			// this packet until it is relevant to be received.
 			//The only alternative is to acknowledge it anyway, and wait for this connection to deadlock and time out the remote side...
 			log_warn!("Major issue, received message way too early, it won't fit the buffer. This connection is ruined!");
-			return;
+			statistics.dropped_messages += 1;
+			return false;
 		}
 		
-		//TODO: Acknowledge
-		
 		//else - Message newer than expected: Just store it!
 		
 		let index = header.sequence_number as usize % WINDOW_SIZE;
-		if let Some(old_message) = &self.cycle_buffer[index] {
-			log_error!("UNEXPECTED UNUSED MESSAGE ON CYCLE BUFFER: ", format!("{:x?}", old_message.header));
+		if self.cycle_buffer[index].is_some() {
+			//Only messages inside of the window are stored, so this is the same message again:
+			log_debug!("Drop packet as it was already received: ", header.sequence_number);
+			statistics.duplicate_messages += 1;
+			return true;
 		}
 		self.cycle_buffer[index] = Some(InternalMessage {
 			header,
 			data,
 		});
+		true
 	}
 }
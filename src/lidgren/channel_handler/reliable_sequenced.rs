@@ -0,0 +1,46 @@
+use crate::prelude::*;
+
+use crate::lidgren::data_structures::{MessageHeader, SEQUENCE_NUMBERS};
+use crate::util::custom_iterator::CustomIterator;
+use crate::lidgren::connection_statistics::ConnectionStatistics;
+
+const WINDOW_SIZE: usize = 64;
+
+//Reliable and sequenced: Every message is acknowledged, but only the newest one is forwarded. Older ones are dropped.
+#[derive(Default)]
+pub struct ReliableSequencedHandler {
+	window_start: u16,
+}
+
+impl ReliableSequencedHandler {
+	pub fn handle(
+		&mut self,
+		header: MessageHeader,
+		mut message_data_iterator: CustomIterator,
+		output_list: &mut Vec<(MessageHeader, Vec<u8>)>,
+		statistics: &mut ConnectionStatistics,
+	) -> bool {
+		let relative_sequence_number = (header.sequence_number as i32 - self.window_start as i32 + 1024 + 512) % 1024 - 512;
+		log_debug!("Window start: ", self.window_start, " Current: ", header.sequence_number, " Relative: ", relative_sequence_number);
+		
+		if relative_sequence_number < 0 {
+			//Received message is older, than what is already processed, so lets just discard it.
+			//It still has to be acknowledged, else the remote would keep resending it.
+			log_debug!("Drop packet as it is too old: ", relative_sequence_number);
+			statistics.dropped_messages += 1;
+			return true;
+		}
+		
+		if relative_sequence_number >= WINDOW_SIZE as i32 {
+			//The remote cannot have sent this message yet, as it would be outside of its window.
+			log_warn!("Major issue, received sequenced message way too early: ", relative_sequence_number);
+			statistics.dropped_messages += 1;
+			return false;
+		}
+		
+		//Everything between the window start and this message is skipped, as it would be outdated once it arrives:
+		self.window_start = (header.sequence_number + 1) % SEQUENCE_NUMBERS;
+		output_list.push((header, message_data_iterator.consume()));
+		true
+	}
+}
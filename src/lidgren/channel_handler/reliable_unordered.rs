@@ -0,0 +1,74 @@
+use crate::prelude::*;
+
+use crate::lidgren::data_structures::{MessageHeader, SEQUENCE_NUMBERS};
+use crate::util::custom_iterator::CustomIterator;
+use crate::lidgren::connection_statistics::ConnectionStatistics;
+
+const WINDOW_SIZE: usize = 64;
+
+//Reliable but unordered: Every message is forwarded as soon as it arrives, but only once.
+pub struct ReliableUnorderedHandler {
+	window_start: u16,
+	early_received: [bool; WINDOW_SIZE],
+}
+
+impl Default for ReliableUnorderedHandler {
+	fn default() -> Self {
+		Self {
+			window_start: 0,
+			early_received: [false; WINDOW_SIZE],
+		}
+	}
+}
+
+impl ReliableUnorderedHandler {
+	pub fn handle(
+		&mut self,
+		header: MessageHeader,
+		mut message_data_iterator: CustomIterator,
+		output_list: &mut Vec<(MessageHeader, Vec<u8>)>,
+		statistics: &mut ConnectionStatistics,
+	) -> bool {
+		let relative_sequence_number = (header.sequence_number as i32 - self.window_start as i32 + 1024 + 512) % 1024 - 512;
+		log_debug!("Window start: ", self.window_start, " Current: ", header.sequence_number, " Relative: ", relative_sequence_number);
+		
+		if relative_sequence_number < 0 {
+			//Received message is older, than what is already processed, so lets just discard it.
+			//It still has to be acknowledged (again), the previous acknowledge probably got lost.
+			log_debug!("Drop packet as it is too old: ", relative_sequence_number);
+			statistics.duplicate_messages += 1;
+			return true;
+		}
+		
+		if relative_sequence_number == 0 {
+			output_list.push((header, message_data_iterator.consume()));
+			self.window_start = (self.window_start + 1) % SEQUENCE_NUMBERS;
+			
+			//Skip over all messages that had been received early:
+			while self.early_received[self.window_start as usize % WINDOW_SIZE] {
+				self.early_received[self.window_start as usize % WINDOW_SIZE] = false;
+				self.window_start = (self.window_start + 1) % SEQUENCE_NUMBERS;
+			}
+			return true;
+		}
+		
+		if relative_sequence_number >= WINDOW_SIZE as i32 {
+			//Same as with the ordered channel, there is no room to remember this message. Has to be ignored.
+			log_warn!("Major issue, received message way too early, it won't fit the buffer. This connection is ruined!");
+			statistics.dropped_messages += 1;
+			return false;
+		}
+		
+		//else - Message newer than expected: Forward it, unless it was already received.
+		
+		let index = header.sequence_number as usize % WINDOW_SIZE;
+		if self.early_received[index] {
+			log_debug!("Drop packet as it was already received: ", header.sequence_number);
+			statistics.duplicate_messages += 1;
+			return true;
+		}
+		self.early_received[index] = true;
+		output_list.push((header, message_data_iterator.consume()));
+		true
+	}
+}
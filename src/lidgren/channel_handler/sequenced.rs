@@ -0,0 +1,34 @@
+use crate::prelude::*;
+
+use crate::lidgren::data_structures::{MessageHeader, SEQUENCE_NUMBERS};
+use crate::util::custom_iterator::CustomIterator;
+use crate::lidgren::connection_statistics::ConnectionStatistics;
+
+//Unreliable, but sequenced: Messages may get lost, but anything older than the last received message is dropped.
+#[derive(Default)]
+pub struct SequencedHandler {
+	expected_sequence_index: u16,
+}
+
+impl SequencedHandler {
+	pub fn handle(
+		&mut self,
+		header: MessageHeader,
+		mut message_data_iterator: CustomIterator,
+		output_list: &mut Vec<(MessageHeader, Vec<u8>)>,
+		statistics: &mut ConnectionStatistics,
+	) {
+		let relative_sequence_number = (header.sequence_number as i32 - self.expected_sequence_index as i32 + 1024 + 512) % 1024 - 512;
+		log_debug!("Expected: ", self.expected_sequence_index, " Current: ", header.sequence_number, " Relative: ", relative_sequence_number);
+		
+		if relative_sequence_number < 0 {
+			//A newer message had already been received, this one is outdated.
+			log_debug!("Drop sequenced packet as it is too old: ", relative_sequence_number);
+			statistics.dropped_messages += 1;
+			return;
+		}
+		
+		self.expected_sequence_index = (header.sequence_number + 1) % SEQUENCE_NUMBERS;
+		output_list.push((header, message_data_iterator.consume()));
+	}
+}
@@ -0,0 +1,17 @@
+use crate::lidgren::data_structures::MessageHeader;
+use crate::util::custom_iterator::CustomIterator;
+
+//Unreliable messages have no state, whatever arrives is forwarded. Exists to keep the channel handlers uniform.
+#[derive(Default)]
+pub struct UnreliableHandler {}
+
+impl UnreliableHandler {
+	pub fn handle(
+		&mut self,
+		header: MessageHeader,
+		mut message_data_iterator: CustomIterator,
+		output_list: &mut Vec<(MessageHeader, Vec<u8>)>,
+	) {
+		output_list.push((header, message_data_iterator.consume()));
+	}
+}
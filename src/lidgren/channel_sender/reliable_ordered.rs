@@ -1,31 +1,44 @@
 use crate::prelude::*;
 
 use std::collections::VecDeque;
-use std::net::SocketAddr;
-use std::time::{Duration, Instant};
-use crate::lidgren::lidgren_server::SocketWrapper;
+use std::time::Instant;
+use crate::lidgren::data_structures::{MessageHeader, SEQUENCE_NUMBERS};
+use crate::lidgren::datagram_builder::DatagramBuilder;
 use crate::lidgren::message_type::MessageType;
+use crate::lidgren::round_trip_time::RoundTripTime;
+use crate::lidgren::congestion_window::CongestionWindow;
 
 const WINDOW_SIZE: usize = 64;
-const SEQUENCE_NUMBERS: usize = 1024;
-//TODO: Use ping time + 0.025...
-const TIME_BETWEEN_RESENDS: Duration = Duration::from_millis(400);
 
+//The sending side of all reliable delivery methods is the same, only the receiver treats them differently.
+//Hence this sender is also used for reliable unordered and reliable sequenced messages.
 pub struct ReliablyOrderedSender {
+	message_type: MessageType,
 	packet_queue: VecDeque<(Vec<u8>, bool)>,
 	message_buffer: [Option<EnqueuedMessage>; WINDOW_SIZE],
 	buffer_oldest: u16,
 	buffer_latest: u16,
+	messages_resent: u64,
+	congestion_window: CongestionWindow,
 }
 
 impl Default for ReliablyOrderedSender {
 	fn default() -> Self {
+		Self::new(MessageType::UserReliableOrdered(0))
+	}
+}
+
+impl ReliablyOrderedSender {
+	pub fn new(message_type: MessageType) -> Self {
 		const INIT: Option<EnqueuedMessage> = None;
 		Self {
+			message_type,
 			packet_queue: VecDeque::new(),
 			message_buffer: [INIT; WINDOW_SIZE],
 			buffer_oldest: 0,
 			buffer_latest: 0,
+			messages_resent: 0,
+			congestion_window: CongestionWindow::new(WINDOW_SIZE),
 		}
 	}
 }
@@ -54,25 +67,30 @@ impl ReliablyOrderedSender {
 		self.packet_queue.push_back((data, is_fragment));
 	}
 	
-	pub fn send_messages(&mut self, address: &SocketAddr, socket: &mut SocketWrapper) {
+	pub fn send_messages(&mut self, datagram: &mut DatagramBuilder, round_trip_time: &RoundTripTime) {
 		for buffered_message in self.message_buffer.iter_mut().flatten() {
-			if !buffered_message.acknowledged && buffered_message.last_sent.elapsed().gt(&TIME_BETWEEN_RESENDS) {
-				socket.send(&buffered_message.data[..], address);
+			if !datagram.has_budget() {
+				return;
+			}
+			if !buffered_message.acknowledged && buffered_message.last_sent.elapsed().gt(&round_trip_time.resend_timeout_with_backoff(buffered_message.sent_count)) {
+				datagram.add_message(&buffered_message.data[..]);
 				buffered_message.last_sent = Instant::now();
 				buffered_message.sent_count += 1;
+				self.messages_resent += 1;
+				self.congestion_window.on_loss(round_trip_time);
 			}
 		}
 		
 		//Queue more message if room for them:
 		let mut space_to_fill = self.get_free_buffer_slots();
-		while space_to_fill > 0 && !self.packet_queue.is_empty() {
+		while space_to_fill > 0 && !self.packet_queue.is_empty() && datagram.has_budget() {
 			log_debug!("+++ Got packet to send! ++++++++++++++++");
 			//Bytes to send:
 			let (data, is_fragment) = self.packet_queue.pop_front().unwrap(); //There should be no reason for this to be 'None' as it is not empty.
 			
 			//Next free sequence number of this packet:
 			let sequence_number = self.buffer_latest;
-			self.buffer_latest = (self.buffer_latest + 1) % SEQUENCE_NUMBERS as u16; //Increment the sequence number.
+			self.buffer_latest = (self.buffer_latest + 1) % SEQUENCE_NUMBERS; //Increment the sequence number.
 			
 			let buffer_index = sequence_number as usize % WINDOW_SIZE;
 			//Sanity check:
@@ -82,16 +100,11 @@ impl ReliablyOrderedSender {
 			
 			log_debug!("Packet is ", data.len(), " bytes");
 			let mut packet_bytes = Vec::with_capacity(5 + data.len());
-			packet_bytes.push(MessageType::UserReliableOrdered(0).to_index());
-			packet_bytes.push((sequence_number << 1) as u8 | is_fragment as u8);
-			packet_bytes.push((sequence_number >> 7) as u8);
-			let length = data.len() * 8;
-			packet_bytes.push(length as u8);
-			packet_bytes.push((length >> 8) as u8);
+			MessageHeader::write(&mut packet_bytes, &self.message_type, is_fragment, sequence_number, data.len());
 			packet_bytes.extend(data);
 			log_debug!("Yielding in ", packet_bytes.len(), " bytes");
 			
-			socket.send(&packet_bytes[..], address); //Copy before storing it, else overhead starts...
+			datagram.add_message(&packet_bytes[..]); //Copy before storing it, else overhead starts...
 			self.message_buffer[buffer_index] = Some(EnqueuedMessage {
 				data: packet_bytes,
 				last_sent: Instant::now(),
@@ -105,6 +118,10 @@ impl ReliablyOrderedSender {
 	
 	pub fn received_acknowledge(&mut self, sequence_number: u16) {
 		//TODO: Add handling, to reset the timeout, if the acknowledge arrived asap.
+		if sequence_number >= SEQUENCE_NUMBERS {
+			log_warn!("Received acknowledge with invalid sequence number ", sequence_number);
+			return;
+		}
 		let relative_sequence_number = ReliablyOrderedSender::create_relative_index(sequence_number, self.buffer_oldest);
 		
 		if relative_sequence_number < 0 {
@@ -112,6 +129,15 @@ impl ReliablyOrderedSender {
 			return;
 		}
 		
+		//Only messages that are sent can be acknowledged:
+		if ReliablyOrderedSender::create_relative_index(sequence_number, self.buffer_latest) < 0 {
+			if let Some(message) = &self.message_buffer[sequence_number as usize % WINDOW_SIZE] {
+				if !message.acknowledged {
+					self.congestion_window.on_acknowledge(message.sent_count);
+				}
+			}
+		}
+		
 		if relative_sequence_number == 0 {
 			//Acknowledge happens in time, oldest message got confirmed!
 			let mut buffered_index = self.buffer_oldest as usize % WINDOW_SIZE;
@@ -122,14 +148,14 @@ impl ReliablyOrderedSender {
 			
 			//Delete the current packet:
 			self.message_buffer[buffered_index] = None;
-			self.buffer_oldest = (self.buffer_oldest + 1) % SEQUENCE_NUMBERS as u16;
+			self.buffer_oldest = (self.buffer_oldest + 1) % SEQUENCE_NUMBERS;
 			//Prepare for next iteration:
 			buffered_index = self.buffer_oldest as usize % WINDOW_SIZE;
 			
 			//Remove all directly following buffered messages from now that got acknowledged.
 			while self.message_buffer[buffered_index].is_some() && self.message_buffer[buffered_index].as_ref().unwrap().acknowledged {
 				self.message_buffer[buffered_index] = None;
-				self.buffer_oldest = (self.buffer_oldest + 1) % SEQUENCE_NUMBERS as u16;
+				self.buffer_oldest = (self.buffer_oldest + 1) % SEQUENCE_NUMBERS;
 				//Prepare for next iteration:
 				buffered_index = self.buffer_oldest as usize % WINDOW_SIZE;
 			}
@@ -147,11 +173,56 @@ impl ReliablyOrderedSender {
 		}
 	}
 	
-	fn create_relative_index(sequence_number: u16, relative_offset: u16) -> i16 {
-		(sequence_number as i16 - relative_offset as i16 + SEQUENCE_NUMBERS as i16 + (SEQUENCE_NUMBERS as i16 / 2)) % SEQUENCE_NUMBERS as i16 - (SEQUENCE_NUMBERS as i16 / 2)
+	//The amount of times the most resent unacknowledged message has been sent:
+	pub fn highest_sent_count(&self) -> u32 {
+		self.message_buffer.iter().flatten()
+			.filter(|message| !message.acknowledged)
+			.map(|message| message.sent_count)
+			.max().unwrap_or(0)
+	}
+	
+	pub fn message_type(&self) -> &MessageType {
+		&self.message_type
+	}
+	
+	//When this sender has something to send next, either new messages or a resend:
+	pub fn next_send_time(&self, round_trip_time: &RoundTripTime) -> Option<Instant> {
+		if !self.packet_queue.is_empty() && self.get_free_buffer_slots() > 0 {
+			return Some(Instant::now());
+		}
+		self.message_buffer.iter().flatten()
+			.filter(|message| !message.acknowledged)
+			.map(|message| message.last_sent + round_trip_time.resend_timeout_with_backoff(message.sent_count))
+			.min()
+	}
+	
+	pub fn messages_resent(&self) -> u64 {
+		self.messages_resent
+	}
+	
+	//Messages that are sent, but not yet acknowledged:
+	pub fn window_occupancy(&self) -> usize {
+		self.message_buffer.iter().flatten().filter(|message| !message.acknowledged).count()
 	}
 	
+	//Messages waiting for room in the window:
+	pub fn queue_length(&self) -> usize {
+		self.packet_queue.len()
+	}
+	
+	//Computed in i32, the sequence number comes from the remote and may be anything:
+	fn create_relative_index(sequence_number: u16, relative_offset: u16) -> i32 {
+		(sequence_number as i32 - relative_offset as i32 + SEQUENCE_NUMBERS as i32 + (SEQUENCE_NUMBERS as i32 / 2)) % SEQUENCE_NUMBERS as i32 - (SEQUENCE_NUMBERS as i32 / 2)
+	}
+	
+	//Room in the window, limited by the congestion window:
 	fn get_free_buffer_slots(&self) -> u8 {
-		(WINDOW_SIZE - (self.buffer_latest as usize + SEQUENCE_NUMBERS - self.buffer_oldest as usize) % SEQUENCE_NUMBERS) as u8
+		let window_space = WINDOW_SIZE - (self.buffer_latest as usize + SEQUENCE_NUMBERS as usize - self.buffer_oldest as usize) % SEQUENCE_NUMBERS as usize;
+		let congestion_space = self.congestion_window.size().saturating_sub(self.window_occupancy());
+		window_space.min(congestion_space) as u8
+	}
+	
+	pub fn congestion_window(&self) -> usize {
+		self.congestion_window.size()
 	}
 }
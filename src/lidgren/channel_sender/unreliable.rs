@@ -0,0 +1,58 @@
+use crate::prelude::*;
+
+use std::collections::VecDeque;
+use crate::lidgren::data_structures::{MessageHeader, SEQUENCE_NUMBERS};
+use crate::lidgren::datagram_builder::DatagramBuilder;
+use crate::lidgren::message_type::MessageType;
+
+//Sends messages exactly once, without waiting for acknowledges.
+//Used for unreliable and (unreliable) sequenced messages, the sequence number only matters to the latter.
+pub struct UnreliableSender {
+	message_type: MessageType,
+	packet_queue: VecDeque<Vec<u8>>,
+	next_sequence_number: u16,
+}
+
+impl Default for UnreliableSender {
+	fn default() -> Self {
+		Self::new(MessageType::UserUnreliable)
+	}
+}
+
+impl UnreliableSender {
+	pub fn new(message_type: MessageType) -> Self {
+		Self {
+			message_type,
+			packet_queue: VecDeque::new(),
+			next_sequence_number: 0,
+		}
+	}
+	
+	pub fn enqueue_packet(&mut self, data: Vec<u8>) {
+		log_debug!("Enqueued unreliable packet with ", data.len(), " bytes");
+		self.packet_queue.push_back(data);
+	}
+	
+	pub fn message_type(&self) -> &MessageType {
+		&self.message_type
+	}
+	
+	pub fn has_pending(&self) -> bool {
+		!self.packet_queue.is_empty()
+	}
+	
+	pub fn send_messages(&mut self, datagram: &mut DatagramBuilder) {
+		while datagram.has_budget() {
+			let Some(data) = self.packet_queue.pop_front() else {
+				break;
+			};
+			let sequence_number = self.next_sequence_number;
+			self.next_sequence_number = (self.next_sequence_number + 1) % SEQUENCE_NUMBERS;
+			
+			let mut packet_bytes = Vec::with_capacity(5 + data.len());
+			MessageHeader::write(&mut packet_bytes, &self.message_type, false, sequence_number, data.len());
+			packet_bytes.extend(data);
+			datagram.add_message(&packet_bytes[..]);
+		}
+	}
+}
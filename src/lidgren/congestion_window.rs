@@ -0,0 +1,57 @@
+use std::time::Instant;
+
+use crate::lidgren::round_trip_time::RoundTripTime;
+
+//Start with a few messages in flight, the window grows quickly while nothing gets lost:
+const INITIAL_WINDOW: f32 = 4.0;
+const MIN_WINDOW: f32 = 1.0;
+
+//Limits how many reliable messages may be unacknowledged at once, similar to TCP (slow start and AIMD).
+//Grows with each message that got acknowledged before it had to be resent, halves when messages get lost.
+//Without this a weak connection gets the whole window at once, loses most of it and has to resend all of it.
+pub struct CongestionWindow {
+	window: f32,
+	slow_start_threshold: f32,
+	max_window: f32,
+	last_decrease: Option<Instant>,
+}
+
+impl CongestionWindow {
+	pub fn new(max_window: usize) -> Self {
+		Self {
+			window: INITIAL_WINDOW.min(max_window as f32),
+			slow_start_threshold: max_window as f32,
+			max_window: max_window as f32,
+			last_decrease: None,
+		}
+	}
+	
+	pub fn size(&self) -> usize {
+		self.window as usize
+	}
+	
+	pub fn on_acknowledge(&mut self, sent_count: u32) {
+		if sent_count != 1 {
+			//It is unknown which of the sent copies got acknowledged, so this says nothing about the connection.
+			return;
+		}
+		if self.window < self.slow_start_threshold {
+			self.window += 1.0; //Slow start, doubles the window each round trip.
+		} else {
+			self.window += 1.0 / self.window; //Grows by one message each round trip.
+		}
+		self.window = self.window.min(self.max_window);
+	}
+	
+	pub fn on_loss(&mut self, round_trip_time: &RoundTripTime) {
+		//Messages that got lost together are one loss event, only shrink once per resend timeout:
+		if let Some(last_decrease) = self.last_decrease {
+			if last_decrease.elapsed().lt(&round_trip_time.resend_timeout()) {
+				return;
+			}
+		}
+		self.slow_start_threshold = (self.window / 2.0).max(2.0);
+		self.window = (self.window / 2.0).max(MIN_WINDOW);
+		self.last_decrease = Some(Instant::now());
+	}
+}
@@ -5,21 +5,57 @@ use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::time::{Duration, Instant};
 use crate::lidgren::channel_handler::reliable_ordered::ReliableOrderedHandler;
+use crate::lidgren::channel_handler::reliable_sequenced::ReliableSequencedHandler;
+use crate::lidgren::channel_handler::reliable_unordered::ReliableUnorderedHandler;
+use crate::lidgren::channel_handler::sequenced::SequencedHandler;
+use crate::lidgren::channel_handler::unreliable::UnreliableHandler;
 use crate::lidgren::channel_sender::reliable_ordered::ReliablyOrderedSender;
+use crate::lidgren::channel_sender::unreliable::UnreliableSender;
 use crate::lidgren::data_structures::MessageHeader;
+use crate::lidgren::datagram_builder::DatagramBuilder;
 use crate::lidgren::data_types::DataType;
+use crate::lidgren::message_type::{CHANNEL_COUNT, MessageType};
+use crate::lidgren::mtu_discovery::MtuDiscovery;
+use crate::lidgren::round_trip_time::RoundTripTime;
 use crate::lidgren::util::formatter as lg_formatter;
 use crate::util::custom_iterator::CustomIterator;
-use crate::lidgren::lidgren_server::{DataPacket, SocketWrapper};
+use crate::lidgren::lidgren_server::{ConnectionSettings, DataPacket};
+use crate::lidgren::transport::Transport;
+use crate::lidgren::connection_statistics::ConnectionStatistics;
+use crate::lidgren::send_priority::SendPriority;
+use crate::lidgren::bandwidth_budget::BandwidthBudget;
 
 pub struct ConnectedClient {
 	pub remote_address: SocketAddr,
-	pub channel_handler: Option<ReliableOrderedHandler>,
+	//Each channel has its own sequence numbers, hence each channel needs its own handler and sender.
+	//The reliable ones are big, so they only get created once a channel is used.
+	unreliable_handler: UnreliableHandler,
+	sequenced_handlers: [SequencedHandler; CHANNEL_COUNT],
+	reliable_unordered_handler: ReliableUnorderedHandler,
+	reliable_sequenced_handlers: [ReliableSequencedHandler; CHANNEL_COUNT],
+	reliable_ordered_handlers: [Option<ReliableOrderedHandler>; CHANNEL_COUNT],
 	fragment_map: HashMap<u32, FragmentData>,
-	channel_sender: ReliablyOrderedSender,
+	unreliable_sender: UnreliableSender,
+	sequenced_senders: [UnreliableSender; CHANNEL_COUNT],
+	reliable_unordered_sender: ReliablyOrderedSender,
+	reliable_sequenced_senders: [Option<ReliablyOrderedSender>; CHANNEL_COUNT],
+	reliable_ordered_senders: [Option<ReliablyOrderedSender>; CHANNEL_COUNT],
 	fragment_group_index: u32,
+	//Acknowledges are collected and sent together, each entry is 3 bytes (message type + sequence number):
+	pending_acknowledges: Vec<u8>,
+	//Ping number and send time of the ping, that is waiting for its pong:
+	pending_ping: Option<(u8, Instant)>,
+	last_ping_sent: Instant,
+	next_ping_number: u8,
+	pub round_trip_time: RoundTripTime,
+	last_received: Instant,
+	mtu_discovery: MtuDiscovery,
+	statistics: ConnectionStatistics,
+	bandwidth_budget: BandwidthBudget,
 }
 
+const TIME_BETWEEN_PINGS: Duration = Duration::from_millis(1000);
+
 macro_rules! vint_size {
 	($a:expr) => {
 		lg_formatter::vint_length($a)
@@ -30,19 +66,73 @@ macro_rules! vint_size {
 }
 
 impl ConnectedClient {
-	pub fn new(remote_address: SocketAddr) -> ConnectedClient {
+	pub fn new(remote_address: SocketAddr, settings: &ConnectionSettings) -> ConnectedClient {
 		ConnectedClient {
 			remote_address,
-			channel_handler: None,
+			unreliable_handler: UnreliableHandler::default(),
+			sequenced_handlers: Default::default(),
+			reliable_unordered_handler: ReliableUnorderedHandler::default(),
+			reliable_sequenced_handlers: Default::default(),
+			reliable_ordered_handlers: Default::default(),
 			fragment_map: HashMap::new(),
-			channel_sender: ReliablyOrderedSender::default(),
+			unreliable_sender: UnreliableSender::new(MessageType::UserUnreliable),
+			sequenced_senders: std::array::from_fn(|channel| UnreliableSender::new(MessageType::UserSequenced(channel as u8))),
+			reliable_unordered_sender: ReliablyOrderedSender::new(MessageType::UserReliableUnordered),
+			reliable_sequenced_senders: Default::default(),
+			reliable_ordered_senders: Default::default(),
 			fragment_group_index: 1, //Just start at 1, 0 is probably possible too.
+			pending_acknowledges: Vec::new(),
+			pending_ping: None,
+			last_ping_sent: Instant::now() - TIME_BETWEEN_PINGS, //Ping right away, to get a round trip time before sending much.
+			next_ping_number: 0,
+			round_trip_time: RoundTripTime::default(),
+			last_received: Instant::now(),
+			mtu_discovery: MtuDiscovery::new(settings.mtu_discovery, settings.max_mtu),
+			statistics: ConnectionStatistics::default(),
+			bandwidth_budget: BandwidthBudget::new(settings.max_bytes_per_second),
 		}
 	}
 	
 	pub fn send_to(&mut self, data: Vec<u8>) {
-		if (data.len() + 5) <= 1408 {
-			self.channel_sender.enqueue_packet(data, false);
+		self.send_with_priority(SendPriority::Normal, data);
+	}
+	
+	pub fn send_with_priority(&mut self, priority: SendPriority, data: Vec<u8>) {
+		self.send_via(priority.message_type(), data);
+	}
+	
+	//Maximum amount of bytes per datagram to this client:
+	pub fn mtu(&self) -> usize {
+		self.mtu_discovery.mtu()
+	}
+	
+	pub fn send_via(&mut self, message_type: MessageType, data: Vec<u8>) {
+		let mtu = self.mtu();
+		if let MessageType::UserSequenced(channel) | MessageType::UserReliableSequenced(channel) | MessageType::UserReliableOrdered(channel) = message_type {
+			if channel as usize >= CHANNEL_COUNT {
+				log_error!("Cannot send messages on channel ", channel, ", there are only ", CHANNEL_COUNT, " channels!");
+				return;
+			}
+		}
+		if let MessageType::UserUnreliable | MessageType::UserSequenced(_) = message_type {
+			if (data.len() + 5) > mtu {
+				//Fragmenting is only worth it, if every fragment is guaranteed to arrive.
+				log_warn!("Dropping unreliable packet, as it does not fit into a single message: ", data.len(), " bytes");
+				return;
+			}
+			match message_type {
+				MessageType::UserSequenced(channel) => self.sequenced_senders[channel as usize].enqueue_packet(data),
+				_ => self.unreliable_sender.enqueue_packet(data),
+			}
+			return;
+		}
+		if !MessageType::is_reliable(&message_type) {
+			log_error!("Cannot send messages of type ", format!("{:?}", message_type), "!");
+			return;
+		}
+		
+		if (data.len() + 5) <= mtu {
+			self.get_or_create_reliable_sender(&message_type).unwrap().enqueue_packet(data, false);
 			return;
 		}
 		
@@ -60,7 +150,7 @@ impl ConnectedClient {
 		}
 		let fragment_total_bits = data.len() as u32 * 8;
 		let constant_header_size = vint_size!(fragment_group_index, fragment_total_bits);
-		let maximum_data_bytes = 1408 - (5 + constant_header_size);
+		let maximum_data_bytes = mtu as u32 - (5 + constant_header_size);
 		
 		let (chunk_size, chunk_count) = figure_out_chunk_stuff(maximum_data_bytes, data.len() as u32);
 		let mut start = 0_usize;
@@ -72,6 +162,7 @@ impl ConnectedClient {
 		lg_formatter::write_vint_32(&mut header, chunk_size);
 		let capacity = chunk_size as usize + header.len() + vint_size!(chunk_count) as usize;
 		
+		let channel_sender = self.get_or_create_reliable_sender(&message_type).unwrap(); //Is reliable, so it will be 'Some'.
 		for index in 0..(chunk_count - 1) {
 			let mut new_data = Vec::with_capacity(capacity);
 			new_data.extend(header.iter());
@@ -79,14 +170,14 @@ impl ConnectedClient {
 			new_data.extend(data[start..end].iter());
 			start = end;
 			end += chunk_size as usize;
-			self.channel_sender.enqueue_packet(new_data, true);
+			channel_sender.enqueue_packet(new_data, true);
 		}
 		{
 			let mut new_data = Vec::with_capacity(capacity);
 			new_data.extend(header.iter());
 			lg_formatter::write_vint_32(&mut new_data, chunk_count - 1);
 			new_data.extend(data[start..].iter());
-			self.channel_sender.enqueue_packet(new_data, true);
+			channel_sender.enqueue_packet(new_data, true);
 		}
 		return;
 		
@@ -115,16 +206,209 @@ impl ConnectedClient {
 		}
 	}
 	
-	pub fn received_acknowledge(&mut self, sequence_id: u16) {
-		self.channel_sender.received_acknowledge(sequence_id);
+	fn get_or_create_reliable_sender(&mut self, message_type: &MessageType) -> Option<&mut ReliablyOrderedSender> {
+		let channel_senders = match *message_type {
+			MessageType::UserReliableUnordered => return Some(&mut self.reliable_unordered_sender),
+			MessageType::UserReliableSequenced(channel) => &mut self.reliable_sequenced_senders[channel as usize],
+			MessageType::UserReliableOrdered(channel) => &mut self.reliable_ordered_senders[channel as usize],
+			_ => return None,
+		};
+		Some(channel_senders.get_or_insert_with(|| ReliablyOrderedSender::new(*message_type)))
+	}
+	
+	pub fn received_acknowledge(&mut self, message_type: &MessageType, sequence_id: u16) -> bool {
+		let channel_sender = match *message_type {
+			MessageType::UserReliableUnordered => Some(&mut self.reliable_unordered_sender),
+			MessageType::UserReliableSequenced(channel) => self.reliable_sequenced_senders[channel as usize].as_mut(),
+			MessageType::UserReliableOrdered(channel) => self.reliable_ordered_senders[channel as usize].as_mut(),
+			_ => None,
+		};
+		//If there is no sender, nothing had been sent with this message type or channel:
+		let channel_sender = unwrap_or_return!(channel_sender, false);
+		channel_sender.received_acknowledge(sequence_id);
+		true
+	}
+	
+	pub fn send_messages(&mut self, transport: &dyn Transport) {
+		//Acknowledges, pings, resends and new messages all share the same datagrams:
+		let mtu = self.mtu();
+		let mut datagram = DatagramBuilder::new(transport, self.remote_address, mtu);
+		datagram.set_budget(self.bandwidth_budget.available(mtu));
+		self.send_acknowledges(&mut datagram);
+		self.send_ping_if_due(&mut datagram);
+		self.send_mtu_probe_if_due(&mut datagram);
+		//Whatever does not fit the budget waits for the next time, so the higher priorities go first:
+		for priority in SendPriority::ALL {
+			let reliable_senders = self.reliable_ordered_senders.iter_mut().flatten()
+				.chain(self.reliable_sequenced_senders.iter_mut().flatten())
+				.chain(std::iter::once(&mut self.reliable_unordered_sender))
+				.filter(|channel_sender| SendPriority::of(channel_sender.message_type()) == priority);
+			for channel_sender in reliable_senders {
+				channel_sender.send_messages(&mut datagram, &self.round_trip_time);
+			}
+			let unreliable_senders = self.sequenced_senders.iter_mut()
+				.chain(std::iter::once(&mut self.unreliable_sender))
+				.filter(|channel_sender| SendPriority::of(channel_sender.message_type()) == priority);
+			for channel_sender in unreliable_senders {
+				channel_sender.send_messages(&mut datagram);
+			}
+		}
+		datagram.flush();
+		let (datagrams_sent, bytes_sent, messages_sent) = datagram.sent_totals();
+		self.bandwidth_budget.spend(bytes_sent as usize);
+		self.statistics.datagrams_sent += datagrams_sent;
+		self.statistics.bytes_sent += bytes_sent;
+		self.statistics.messages_sent += messages_sent;
+	}
+	
+	fn queue_acknowledge(&mut self, message_type: &MessageType, sequence_number: u16) {
+		self.pending_acknowledges.push(message_type.to_index());
+		self.pending_acknowledges.push(sequence_number as u8);
+		self.pending_acknowledges.push((sequence_number >> 8) as u8);
+	}
+	
+	fn send_acknowledges(&mut self, datagram: &mut DatagramBuilder) {
+		//As many acknowledges as fit into a single message, without exceeding the MTU:
+		let max_acknowledge_bytes = (datagram.mtu() - 5) / 3 * 3;
+		for acknowledges in self.pending_acknowledges.chunks(max_acknowledge_bytes) {
+			log_debug!("Sending ", acknowledges.len() / 3, " acknowledges");
+			let mut result_buffer = Vec::with_capacity(5 + acknowledges.len());
+			MessageHeader::write(&mut result_buffer, &MessageType::Acknowledge, false, 0, acknowledges.len());
+			result_buffer.extend_from_slice(acknowledges);
+			datagram.add_message(&result_buffer);
+		}
+		self.pending_acknowledges.clear();
+	}
+	
+	fn send_ping_if_due(&mut self, datagram: &mut DatagramBuilder) {
+		if self.last_ping_sent.elapsed().lt(&TIME_BETWEEN_PINGS) {
+			return;
+		}
+		//If the previous ping is still pending, its pong got lost - just replace it.
+		let ping_number = self.next_ping_number;
+		self.next_ping_number = self.next_ping_number.wrapping_add(1);
+		
+		let mut result_buffer = Vec::with_capacity(5 + 1);
+		MessageHeader::write(&mut result_buffer, &MessageType::Ping, false, 0, 1);
+		result_buffer.push(ping_number);
+		datagram.add_message(&result_buffer);
+		
+		let now = Instant::now();
+		self.last_ping_sent = now;
+		self.pending_ping = Some((ping_number, now));
+	}
+	
+	fn send_mtu_probe_if_due(&mut self, datagram: &mut DatagramBuilder) {
+		//Wait for the round trip time first, else each probe would be waited for way too long (or too short):
+		let Some(smoothed_round_trip_time) = self.round_trip_time.smoothed() else {
+			return;
+		};
+		let Some(probe_size) = self.mtu_discovery.next_probe(ConnectedClient::mtu_probe_timeout(smoothed_round_trip_time)) else {
+			return;
+		};
+		log_debug!("[MTU] Sending probe with ", probe_size, " bytes");
+		let mut result_buffer = Vec::with_capacity(probe_size);
+		MessageHeader::write(&mut result_buffer, &MessageType::ExpandMTURequest, false, 0, probe_size - 5);
+		result_buffer.resize(probe_size, 0);
+		datagram.send_standalone(&result_buffer);
+	}
+	
+	fn mtu_probe_timeout(smoothed_round_trip_time: Duration) -> Duration {
+		smoothed_round_trip_time * 2 + Duration::from_millis(100)
 	}
 	
-	pub fn send_messages(&mut self, socket: &mut SocketWrapper) {
-		self.channel_sender.send_messages(&self.remote_address, socket);
+	//The next time 'send_messages' has something to do, be it acknowledges, pings, probes, new messages or resends:
+	pub fn next_deadline(&self) -> Instant {
+		let now = Instant::now();
+		if !self.pending_acknowledges.is_empty() {
+			return now;
+		}
+		let mut deadline = self.last_ping_sent + TIME_BETWEEN_PINGS;
+		if let Some(smoothed_round_trip_time) = self.round_trip_time.smoothed() {
+			if let Some(probe_time) = self.mtu_discovery.next_probe_time(ConnectedClient::mtu_probe_timeout(smoothed_round_trip_time)) {
+				deadline = deadline.min(probe_time);
+			}
+		}
+		//Messages are only sent, when the budget allows it:
+		let unreliable_pending = self.unreliable_sender.has_pending()
+			|| self.sequenced_senders.iter().any(|channel_sender| channel_sender.has_pending());
+		let mut send_deadline = if unreliable_pending { Some(now) } else { None };
+		let reliable_senders = self.reliable_ordered_senders.iter().flatten()
+			.chain(self.reliable_sequenced_senders.iter().flatten())
+			.chain(std::iter::once(&self.reliable_unordered_sender));
+		for channel_sender in reliable_senders {
+			if let Some(send_time) = channel_sender.next_send_time(&self.round_trip_time) {
+				send_deadline = Some(send_deadline.map_or(send_time, |send_deadline| send_deadline.min(send_time)));
+			}
+		}
+		if let Some(send_deadline) = send_deadline {
+			let budget_time = self.bandwidth_budget.next_available_time(self.mtu());
+			deadline = deadline.min(budget_time.map_or(send_deadline, |budget_time| send_deadline.max(budget_time)));
+		}
+		deadline
 	}
 	
-	pub fn heartbeat(&mut self) {
-		self.cleanup();
+	pub fn received_mtu_success(&mut self, payload_size: usize) {
+		//The remote reports the payload size, the header is not part of it:
+		self.mtu_discovery.probe_succeeded(payload_size + 5);
+	}
+	
+	pub fn received_pong(&mut self, ping_number: u8) {
+		let (pending_number, sent_time) = unwrap_or_return!(self.pending_ping, {
+			log_debug!("Received pong ", ping_number, ", but no ping is pending.");
+		});
+		if pending_number != ping_number {
+			log_debug!("Received pong ", ping_number, ", but ping ", pending_number, " is pending.");
+			return;
+		}
+		self.pending_ping = None;
+		self.round_trip_time.add_sample(sent_time.elapsed());
+		log_debug!("Round trip time is now ", format!("{:?}", self.round_trip_time.smoothed().unwrap()), " resend timeout ", format!("{:?}", self.round_trip_time.resend_timeout()));
+	}
+	
+	pub fn received_datagram(&mut self, size: usize) {
+		self.last_received = Instant::now();
+		self.statistics.datagrams_received += 1;
+		self.statistics.bytes_received += size as u64;
+	}
+	
+	pub fn received_message(&mut self) {
+		self.statistics.messages_received += 1;
+	}
+	
+	//Snapshot of the counters, completed with the current state of the connection:
+	pub fn statistics(&self) -> ConnectionStatistics {
+		let reliable_senders = || self.reliable_ordered_senders.iter().flatten()
+			.chain(self.reliable_sequenced_senders.iter().flatten())
+			.chain(std::iter::once(&self.reliable_unordered_sender));
+		let mut statistics = self.statistics.clone();
+		statistics.messages_resent = reliable_senders().map(|channel_sender| channel_sender.messages_resent()).sum();
+		statistics.send_window_occupancy = reliable_senders().map(|channel_sender| channel_sender.window_occupancy()).sum();
+		statistics.send_queue_length = reliable_senders().map(|channel_sender| channel_sender.queue_length()).sum();
+		statistics.busiest_channel_congestion_window = reliable_senders()
+			.max_by_key(|channel_sender| channel_sender.window_occupancy() + channel_sender.queue_length())
+			.map(|channel_sender| channel_sender.congestion_window())
+			.unwrap_or_default();
+		statistics.fragment_groups_unfinished = self.fragment_map.values().filter(|data| !data.is_complete()).count();
+		statistics.round_trip_time = self.round_trip_time.smoothed();
+		statistics.mtu = self.mtu();
+		statistics
+	}
+	
+	//Returns the event type (timed out or kicked) and reason, if the connection should be dropped:
+	pub fn heartbeat(&mut self, settings: &ConnectionSettings) -> Option<(DataType, String)> {
+		if self.last_received.elapsed().gt(&settings.inactivity_timeout) {
+			return Some((DataType::TimedOut, format!("Connection timed out, nothing received for {} seconds", self.last_received.elapsed().as_secs())));
+		}
+		let highest_sent_count = self.reliable_ordered_senders.iter().flatten()
+			.chain(self.reliable_sequenced_senders.iter().flatten())
+			.chain(std::iter::once(&self.reliable_unordered_sender))
+			.map(|channel_sender| channel_sender.highest_sent_count())
+			.max().unwrap_or(0);
+		if highest_sent_count > settings.max_resend_count {
+			return Some((DataType::TimedOut, format!("Connection is dead, message was not acknowledged after {} sends", highest_sent_count)));
+		}
+		self.cleanup().map(|reason| (DataType::Kicked, reason))
 	}
 	
 	pub fn handle_new_message(&mut self,
@@ -132,52 +416,102 @@ impl ConnectedClient {
 	                          address: SocketAddr,
 	                          header: MessageHeader,
 	                          message_data_iterator: CustomIterator,
-	) {
-		if self.channel_handler.is_none() {
-			self.channel_handler = Some(ReliableOrderedHandler::default())
-		}
-		let channel = self.channel_handler.as_mut().unwrap();
+	                          settings: &ConnectionSettings,
+	) -> Option<String> {
 		let mut output_list_to_make_rust_compiler_happy = Vec::new();
-		channel.handle(header, message_data_iterator, &mut output_list_to_make_rust_compiler_happy);
+		//The header is consumed by the handler, keep what is needed to acknowledge it:
+		let (message_type, sequence_number) = (header.message_type, header.sequence_number);
+		let acknowledge = match header.message_type {
+			MessageType::UserUnreliable => {
+				self.unreliable_handler.handle(header, message_data_iterator, &mut output_list_to_make_rust_compiler_happy);
+				false
+			}
+			MessageType::UserSequenced(channel) => {
+				self.sequenced_handlers[channel as usize].handle(header, message_data_iterator, &mut output_list_to_make_rust_compiler_happy, &mut self.statistics);
+				false
+			}
+			MessageType::UserReliableUnordered => {
+				self.reliable_unordered_handler.handle(header, message_data_iterator, &mut output_list_to_make_rust_compiler_happy, &mut self.statistics)
+			}
+			MessageType::UserReliableSequenced(channel) => {
+				self.reliable_sequenced_handlers[channel as usize].handle(header, message_data_iterator, &mut output_list_to_make_rust_compiler_happy, &mut self.statistics)
+			}
+			MessageType::UserReliableOrdered(channel) => {
+				let channel = self.reliable_ordered_handlers[channel as usize].get_or_insert_with(ReliableOrderedHandler::default);
+				channel.handle(header, message_data_iterator, &mut output_list_to_make_rust_compiler_happy, &mut self.statistics)
+			}
+			_ => {
+				log_warn!("Cannot handle message type ", format!("{:?}", header.message_type), " yet!");
+				return None;
+			}
+		};
+		if acknowledge {
+			self.queue_acknowledge(&message_type, sequence_number);
+		}
 		for forward_message in output_list_to_make_rust_compiler_happy {
-			self.handle(new_packets, address, forward_message.0, forward_message.1);
+			let kick_reason = self.handle(new_packets, address, forward_message.0, forward_message.1, settings);
+			if kick_reason.is_some() {
+				return kick_reason;
+			}
 		}
+		None
 	}
 	
+	//Returns a reason, if the remote misbehaved so badly, that it has to be kicked:
 	pub fn handle(&mut self,
 	              new_packets: &mut Vec<DataPacket>,
 	              address: SocketAddr,
 	              header: MessageHeader,
 	              data: Vec<u8>,
-	) {
+	              settings: &ConnectionSettings,
+	) -> Option<String> {
 		if !header.fragment {
 			new_packets.push(DataPacket {
 				data_type: DataType::Data,
 				remote_address: address,
 				data,
 			});
-			return;
+			return None;
 		}
 		//Else we got a fragment to handle, read header:
 		let mut iterator = CustomIterator::borrow(&data[..]);
-		if iterator.remaining() < 123 {
-			log_warn!("Not enough bytes to read fragment header: ", iterator.remaining(), "/", 122);
-			return;
+		if iterator.remaining() < 4 {
+			//Each of the four header values takes at least one byte:
+			log_warn!("Not enough bytes to read fragment header: ", iterator.remaining(), "/", 4);
+			return None;
+		}
+		let fragment_group_id = unwrap_or_print_return!(lg_formatter::read_vint_32(&mut iterator).wrap(ex!("While reading 'fragment_group_id'")), None);
+		let fragment_bits = unwrap_or_print_return!(lg_formatter::read_vint_32(&mut iterator).wrap(ex!("While reading 'fragment_bits'")), None);
+		let fragment_chunk_size = unwrap_or_print_return!(lg_formatter::read_vint_32(&mut iterator).wrap(ex!("While reading 'fragment_chunk_size'")), None);
+		let fragment_index = unwrap_or_print_return!(lg_formatter::read_vint_32(&mut iterator).wrap(ex!("While reading 'fragment_index'")), None);
+		
+		//All sizes are announced by the remote, validate them before allocating anything:
+		if fragment_chunk_size == 0 || fragment_chunk_size > settings.max_fragment_chunk_size {
+			return Some(format!("Sent fragment with invalid chunk size {} / {}", fragment_chunk_size, settings.max_fragment_chunk_size));
 		}
-		let fragment_group_id = unwrap_or_print_return!(lg_formatter::read_vint_32(&mut iterator).wrap(ex!("While reading 'fragment_group_id'")));
-		let fragment_bits = unwrap_or_print_return!(lg_formatter::read_vint_32(&mut iterator).wrap(ex!("While reading 'fragment_bits'")));
-		let fragment_chunk_size = unwrap_or_print_return!(lg_formatter::read_vint_32(&mut iterator).wrap(ex!("While reading 'fragment_chunk_size'")));
-		let fragment_index = unwrap_or_print_return!(lg_formatter::read_vint_32(&mut iterator).wrap(ex!("While reading 'fragment_index'")));
 		
 		//Copy code from original:
-		let _total_bytes = (fragment_bits + 7) / 8;
+		let _total_bytes = fragment_bits.div_ceil(8); //Announced by the remote, may not overflow.
 		let mut _total_num_of_chunks = _total_bytes / fragment_chunk_size;
 		if _total_num_of_chunks * fragment_chunk_size < _total_bytes {
 			_total_num_of_chunks += 1;
 		}
 		if fragment_index >= _total_num_of_chunks {
 			log_warn!("Remote sent invalid fragment packet, index of fragment bigger than fragment count: ", fragment_index, " / ", _total_num_of_chunks);
-			return;
+			return None;
+		}
+		
+		if !self.fragment_map.contains_key(&fragment_group_id) {
+			//Only unfinished groups occupy memory, finished ones only linger to catch duplicates:
+			let (unfinished_groups, unfinished_bytes) = self.fragment_map.values()
+				.filter(|data| !data.is_complete())
+				.fold((0, 0), |(groups, bytes), data| (groups + 1, bytes + data.buffer.len()));
+			if unfinished_groups >= settings.max_fragment_groups {
+				return Some(format!("Sent too many fragmented messages at once, limit is {}", settings.max_fragment_groups));
+			}
+			if unfinished_bytes + _total_bytes as usize > settings.max_fragment_bytes {
+				return Some(format!("Sent fragmented messages with more than {} bytes in total", settings.max_fragment_bytes));
+			}
 		}
 		
 		let fragment_data = match self.fragment_map.entry(fragment_group_id) {
@@ -186,16 +520,17 @@ impl ConnectedClient {
 				fragment_data.last_accessed_time = Instant::now(); //Update last touch time, to properly get rid of it.
 				if _total_num_of_chunks != fragment_data.announced_chunk_count {
 					log_warn!("Remote sent invalid fragment packet, new fragment chunk count ", _total_num_of_chunks, " does not match original ", fragment_data.announced_chunk_count);
-					return;
+					return None;
 				}
 				if fragment_bits != fragment_data.announced_chunk_bits {
 					log_warn!("Remote sent invalid fragment packet, new fragment chunk bit size ", fragment_bits, " does not match original ", fragment_data.announced_chunk_bits);
-					return;
+					return None;
 				}
 				fragment_data
 			}
 			Entry::Vacant(e) => {
 				log_debug!("[Fragment] Received new fragment ", fragment_group_id, " with ", _total_num_of_chunks, " chunks each ", fragment_chunk_size, " bytes (", fragment_bits, " bits).");
+				self.statistics.fragment_groups_started += 1;
 				
 				let fragment_data = FragmentData {
 					last_accessed_time: Instant::now(),
@@ -212,50 +547,45 @@ impl ConnectedClient {
 		log_debug!("[Fragment] Got new fragment ", fragment_group_id, " with index ", fragment_index, " / ", _total_num_of_chunks);
 		if fragment_data.chunk_checklist[fragment_index as usize] {
 			log_debug!("[Fragment] -> already received!");
+			self.statistics.duplicate_messages += 1;
+			return None;
+		}
+		
+		let remaining = iterator.remaining();
+		let expected = if fragment_index == _total_num_of_chunks - 1 {
+			_total_bytes - fragment_index * fragment_chunk_size
 		} else {
-			fragment_data.chunk_checklist[fragment_index as usize] = true;
-			fragment_data.chunk_amount += 1;
-			let buffer = &mut fragment_data.buffer[..];
-			let section = &mut buffer[(fragment_index * fragment_chunk_size) as usize..]; //Starting offset until whenever...
-			
-			let remaining = iterator.remaining();
-			if remaining > fragment_chunk_size as usize {
-				//Illegal size, might blow the buffer!
-				//TODO: Disconnect this malicious client!
-				log_warn!("[Fragment] DANGER: Fragment does have a size bigger than the chunk size ", remaining, " / ", _total_bytes);
-				return;
-			}
-			if fragment_index == _total_num_of_chunks - 1 {
-				let expected = _total_bytes % fragment_chunk_size;
-				if remaining < expected as usize {
-					log_warn!("[Fragment] WARNING: Fragment does not have expected size ", remaining, " / ", expected);
-				} else if remaining > expected as usize {
-					//TODO: Disconnect dangerous client!
-					log_warn!("[Fragment] DANGER: Last Fragment is too big ", remaining, " / ", expected);
-					return;
-				}
-			} else {
-				if remaining != fragment_chunk_size as usize {
-					log_warn!("[Fragment] WARNING: Fragment does not have expected size ", remaining, " / ", fragment_chunk_size);
-				}
-			}
-			let remaining_bytes = iterator.consume();
-			section.copy_from_slice(&remaining_bytes[..]);
-			
-			if fragment_data.is_complete() {
-				let buffer = std::mem::replace(&mut fragment_data.buffer, Vec::with_capacity(0));
-				new_packets.push(DataPacket {
-					data_type: DataType::Data,
-					remote_address: address,
-					data: buffer,
-				});
-			}
+			fragment_chunk_size
+		};
+		if remaining > expected as usize {
+			//Illegal size, would blow the buffer!
+			return Some(format!("Sent fragment bigger than announced {} / {}", remaining, expected));
+		}
+		if remaining < expected as usize {
+			log_warn!("[Fragment] WARNING: Fragment does not have expected size ", remaining, " / ", expected);
+		}
+		fragment_data.chunk_checklist[fragment_index as usize] = true;
+		fragment_data.chunk_amount += 1;
+		let offset = (fragment_index * fragment_chunk_size) as usize;
+		let remaining_bytes = iterator.consume();
+		fragment_data.buffer[offset..(offset + remaining)].copy_from_slice(&remaining_bytes[..]);
+		
+		if fragment_data.is_complete() {
+			let buffer = std::mem::replace(&mut fragment_data.buffer, Vec::with_capacity(0));
+			self.statistics.fragment_groups_completed += 1;
+			new_packets.push(DataPacket {
+				data_type: DataType::Data,
+				remote_address: address,
+				data: buffer,
+			});
 		}
+		None
 	}
 	
-	pub fn cleanup(&mut self) {
+	pub fn cleanup(&mut self) -> Option<String> {
 		//Cleanup old fragments...
 		let mut to_remove_keys = Vec::new();
+		let mut kick_reason = None;
 		let max_time = Duration::from_millis(10000);
 		for (group, data) in self.fragment_map.iter() {
 			let elapsed = data.last_accessed_time.elapsed();
@@ -263,8 +593,9 @@ impl ConnectedClient {
 				if data.is_complete() {
 					log_debug!("Removing completed fragment group '", group, "' as it is older than 10 seconds. In fact: ", elapsed.as_millis());
 				} else {
-					//TODO: Kick the connection!
 					log_warn!("Removing UNFINISHED! fragment group '", group, "' as it is older than 10 seconds. In fact: ", elapsed.as_millis());
+					//The remote stopped sending a message half way, something is broken:
+					kick_reason = Some(format!("Fragmented message {} was not completed in time", group));
 				}
 				to_remove_keys.push(*group);
 			}
@@ -272,6 +603,7 @@ impl ConnectedClient {
 		for group in to_remove_keys {
 			self.fragment_map.remove(&group);
 		}
+		kick_reason
 	}
 }
 
@@ -0,0 +1,34 @@
+use std::time::Duration;
+
+//Counters about the health of a connection, to find out why a client lags or what hogs the bandwidth.
+//The counters are totals since the connection got established, the rest is the state at the time of the query.
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionStatistics {
+	//All datagrams built for this connection, Lidgren headers included:
+	pub datagrams_sent: u64,
+	pub bytes_sent: u64,
+	//Lidgren messages of any type, including acknowledges and pings:
+	pub messages_sent: u64,
+	//Reliable messages that got sent again, as they were not acknowledged in time:
+	pub messages_resent: u64,
+	pub datagrams_received: u64,
+	pub bytes_received: u64,
+	pub messages_received: u64,
+	//Reliable messages that had already been received before:
+	pub duplicate_messages: u64,
+	//Messages that got thrown away, as they were outdated or arrived too early:
+	pub dropped_messages: u64,
+	pub fragment_groups_started: u64,
+	pub fragment_groups_completed: u64,
+	//Current state:
+	pub fragment_groups_unfinished: usize,
+	//Reliable messages which are sent, but not yet acknowledged, summed over all channels:
+	pub send_window_occupancy: usize,
+	//Reliable messages waiting for room in the send window, summed over all channels:
+	pub send_queue_length: usize,
+	//Every channel has its own congestion window. This is the one of the channel with the most messages
+	// sent or waiting, as it is the one that limits the throughput:
+	pub busiest_channel_congestion_window: usize,
+	pub round_trip_time: Option<Duration>,
+	pub mtu: usize,
+}
@@ -4,6 +4,10 @@ use crate::lidgren::message_type::MessageType;
 use crate::util::custom_iterator::CustomIterator;
 
 pub const MESSAGE_HEADER_LENGTH: usize = 5;
+//Lidgren sequence numbers wrap at this value, the header has room for larger ones:
+pub const SEQUENCE_NUMBERS: u16 = 1024;
+//Maximum amount of bytes per UDP datagram, that is safe to be sent:
+pub const DEFAULT_MTU: usize = 1408;
 
 #[derive(Debug)]
 pub struct MessageHeader {
@@ -28,6 +32,9 @@ impl MessageHeader {
 		
 		let message_type = MessageType::from_id(message_type_id)
 			.map_ex(ex!("There was no message type for id: ", message_type_id))?;
+		if sequence_number >= SEQUENCE_NUMBERS {
+			return exception!("Sequence number out of range: ", sequence_number, "/", SEQUENCE_NUMBERS);
+		}
 		
 		//Make sure to not overflow:
 		let bytes = if bits >= (0xFFFF - 8) { 0xFFFF / 8 } else { (bits + 7) / 8 };
@@ -40,4 +47,13 @@ impl MessageHeader {
 			bytes,
 		})
 	}
+	
+	pub fn write(buffer: &mut Vec<u8>, message_type: &MessageType, fragment: bool, sequence_number: u16, payload_bytes: usize) {
+		buffer.push(message_type.to_index());
+		buffer.push((sequence_number << 1) as u8 | fragment as u8);
+		buffer.push((sequence_number >> 7) as u8);
+		let length = payload_bytes * 8;
+		buffer.push(length as u8);
+		buffer.push((length >> 8) as u8);
+	}
 }
@@ -3,4 +3,14 @@ pub enum DataType {
 	Discovery,
 	Connect,
 	Data,
+	//Connection lifecycle events, so that the application can react to them.
+	//Disconnected and TimedOut also end handshakes, which never got Connected:
+	//The remote finished the Lidgren handshake, data is empty:
+	Connected,
+	//The remote disconnected, data contains its reason:
+	Disconnected,
+	//The remote stopped responding, data contains the details:
+	TimedOut,
+	//The server dropped the connection, data contains the reason:
+	Kicked,
 }
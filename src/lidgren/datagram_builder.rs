@@ -0,0 +1,90 @@
+use crate::prelude::*;
+
+use std::net::SocketAddr;
+use crate::lidgren::transport::Transport;
+
+//Collects outgoing Lidgren messages and packs as many as possible into one UDP datagram.
+//The receiving side already reads multiple messages per datagram, this saves the per-packet overhead.
+pub struct DatagramBuilder<'a> {
+	transport: &'a dyn Transport,
+	address: SocketAddr,
+	mtu: usize,
+	buffer: Vec<u8>,
+	message_count: u32,
+	//Bytes that may be sent in total, None if unlimited:
+	budget: Option<usize>,
+	//Totals over all datagrams sent by this builder:
+	datagrams_sent: u64,
+	bytes_sent: u64,
+	messages_sent: u64,
+}
+
+impl<'a> DatagramBuilder<'a> {
+	pub fn new(transport: &'a dyn Transport, address: SocketAddr, mtu: usize) -> Self {
+		Self {
+			transport,
+			address,
+			mtu,
+			buffer: Vec::with_capacity(mtu),
+			message_count: 0,
+			budget: None,
+			datagrams_sent: 0,
+			bytes_sent: 0,
+			messages_sent: 0,
+		}
+	}
+	
+	pub fn mtu(&self) -> usize {
+		self.mtu
+	}
+	
+	pub fn set_budget(&mut self, budget: Option<usize>) {
+		self.budget = budget;
+	}
+	
+	//Senders stop adding messages once the budget is used up. Acknowledges and pings ignore it, they are tiny
+	// and the connection would suffer without them.
+	pub fn has_budget(&self) -> bool {
+		self.budget.is_none_or(|budget| self.bytes_sent as usize + self.buffer.len() < budget)
+	}
+	
+	//The message has to be a complete Lidgren message, header included:
+	pub fn add_message(&mut self, message: &[u8]) {
+		if self.buffer.len() + message.len() > self.mtu {
+			self.flush();
+		}
+		if message.len() > self.mtu {
+			//Should not happen, as big messages get fragmented. Send it anyway, the network might manage.
+			log_warn!("Sending message of ", message.len(), " bytes, which is bigger than the MTU ", self.mtu);
+		}
+		self.buffer.extend_from_slice(message);
+		self.message_count += 1;
+	}
+	
+	//For messages that must not share a datagram with others (like MTU probes):
+	pub fn send_standalone(&mut self, message: &[u8]) {
+		self.flush();
+		self.transport.send(message, &self.address);
+		self.datagrams_sent += 1;
+		self.bytes_sent += message.len() as u64;
+		self.messages_sent += 1;
+	}
+	
+	pub fn flush(&mut self) {
+		if self.buffer.is_empty() {
+			return;
+		}
+		log_debug!("Sending datagram with ", self.message_count, " messages and ", self.buffer.len(), " bytes");
+		self.transport.send(&self.buffer, &self.address);
+		self.datagrams_sent += 1;
+		self.bytes_sent += self.buffer.len() as u64;
+		self.messages_sent += self.message_count as u64;
+		self.buffer.clear();
+		self.message_count = 0;
+	}
+	
+	//Datagrams, bytes and messages sent so far, only complete after flushing:
+	pub fn sent_totals(&self) -> (u64, u64, u64) {
+		(self.datagrams_sent, self.bytes_sent, self.messages_sent)
+	}
+}
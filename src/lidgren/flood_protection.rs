@@ -0,0 +1,134 @@
+use crate::prelude::*;
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+//Sources that did not send anything for this long are forgotten, their buckets would be full again anyway:
+const FORGET_SOURCE_DURATION: Duration = Duration::from_secs(60);
+
+//Messages that anyone can send without being connected, each kind has its own limit:
+#[derive(Debug, Clone, Copy)]
+pub enum UnconnectedTraffic {
+	Discovery,
+	Connect,
+	//Datagrams that could not be parsed, or make no sense for an unconnected remote:
+	Malformed,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+	pub per_second: f32,
+	//Amount that may arrive at once, before the rate applies:
+	pub burst: f32,
+}
+
+pub struct FloodProtectionSettings {
+	//Discovery responses are bigger than the requests, without a limit the server could be used to amplify traffic:
+	pub discovery: RateLimit,
+	//Lidgren resends the connect message a few times on its own:
+	pub connect: RateLimit,
+	pub malformed: RateLimit,
+	//Sources exceeding any limit are ignored for this long (only while not connected):
+	pub block_duration: Duration,
+}
+
+impl Default for FloodProtectionSettings {
+	fn default() -> Self {
+		Self {
+			discovery: RateLimit { per_second: 2.0, burst: 10.0 },
+			connect: RateLimit { per_second: 1.0, burst: 10.0 },
+			malformed: RateLimit { per_second: 1.0, burst: 10.0 },
+			block_duration: Duration::from_secs(60),
+		}
+	}
+}
+
+struct TokenBucket {
+	tokens: f32,
+	last_refill: Instant,
+}
+
+impl TokenBucket {
+	fn new(limit: &RateLimit, now: Instant) -> Self {
+		Self {
+			tokens: limit.burst,
+			last_refill: now,
+		}
+	}
+	
+	fn take(&mut self, limit: &RateLimit, now: Instant) -> bool {
+		self.tokens = (self.tokens + (now - self.last_refill).as_secs_f32() * limit.per_second).min(limit.burst);
+		self.last_refill = now;
+		if self.tokens < 1.0 {
+			return false;
+		}
+		self.tokens -= 1.0;
+		true
+	}
+}
+
+struct Source {
+	discovery: TokenBucket,
+	connect: TokenBucket,
+	malformed: TokenBucket,
+	blocked_until: Option<Instant>,
+	last_seen: Instant,
+}
+
+//Per IP rate limits for traffic of remotes that are not connected.
+//Connected clients are never blocked, as the IP could be shared with (or spoofed by) someone else.
+pub struct FloodProtection {
+	pub settings: FloodProtectionSettings,
+	sources: HashMap<IpAddr, Source>,
+}
+
+impl FloodProtection {
+	pub fn new(settings: FloodProtectionSettings) -> Self {
+		Self {
+			settings,
+			sources: HashMap::new(),
+		}
+	}
+	
+	pub fn is_blocked(&self, address: &IpAddr) -> bool {
+		self.sources.get(address)
+			.and_then(|source| source.blocked_until)
+			.is_some_and(|blocked_until| blocked_until > Instant::now())
+	}
+	
+	//Returns false, if the traffic exceeds the limit and should be ignored. The source gets blocked then:
+	pub fn allow(&mut self, address: IpAddr, traffic: UnconnectedTraffic) -> bool {
+		let now = Instant::now();
+		let settings = &self.settings;
+		let source = self.sources.entry(address).or_insert_with(|| Source {
+			discovery: TokenBucket::new(&settings.discovery, now),
+			connect: TokenBucket::new(&settings.connect, now),
+			malformed: TokenBucket::new(&settings.malformed, now),
+			blocked_until: None,
+			last_seen: now,
+		});
+		source.last_seen = now;
+		if source.blocked_until.is_some_and(|blocked_until| blocked_until > now) {
+			return false;
+		}
+		let allowed = match traffic {
+			UnconnectedTraffic::Discovery => source.discovery.take(&settings.discovery, now),
+			UnconnectedTraffic::Connect => source.connect.take(&settings.connect, now),
+			UnconnectedTraffic::Malformed => source.malformed.take(&settings.malformed, now),
+		};
+		if !allowed {
+			log_warn!("Blocking ", address, " for ", settings.block_duration.as_secs(), " seconds, it exceeded the rate limit for ", format!("{:?}", traffic), " messages.");
+			source.blocked_until = Some(now + settings.block_duration);
+		}
+		allowed
+	}
+	
+	pub fn cleanup(&mut self) {
+		let now = Instant::now();
+		self.sources.retain(|_, source| {
+			source.blocked_until.is_some_and(|blocked_until| blocked_until > now)
+				|| now - source.last_seen < FORGET_SOURCE_DURATION
+		});
+	}
+}
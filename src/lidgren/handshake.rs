@@ -0,0 +1,134 @@
+use crate::prelude::*;
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+pub struct HandshakeSettings {
+	//The application has to approve or reject a connect within this time:
+	pub approval_timeout: Duration,
+	//The connect response is resent this often, until the remote confirms it:
+	pub resend_interval: Duration,
+	//Remotes that did not confirm the connect response after it got sent this many times are given up on:
+	pub max_response_attempts: u32,
+}
+
+impl Default for HandshakeSettings {
+	fn default() -> Self {
+		Self {
+			approval_timeout: Duration::from_secs(15),
+			//Same as Lidgren's defaults:
+			resend_interval: Duration::from_secs(3),
+			max_response_attempts: 5,
+		}
+	}
+}
+
+enum HandshakeState {
+	//Connect got received, the application decides whether the remote may join:
+	AwaitingApproval { since: Instant },
+	//Connect response got sent, waiting for the remote to confirm it with ConnectionEstablished:
+	ResponseSent { last_sent: Instant, sent_count: u32 },
+}
+
+//What to do with a received Connect message:
+pub enum ConnectAction {
+	//New handshake, the application has to approve it:
+	AskApplication,
+	//The remote did not get the response yet, send it again:
+	ResendResponse,
+	//Still waiting for the application:
+	Ignore,
+}
+
+//Remotes in the middle of connecting. Once established, they are no longer in here, but a ConnectedClient.
+//Only remotes which got approved may establish a connection.
+#[derive(Default)]
+pub struct Handshakes {
+	pending: HashMap<SocketAddr, HandshakeState>,
+}
+
+impl Handshakes {
+	pub fn received_connect(&mut self, address: SocketAddr) -> ConnectAction {
+		match self.pending.get(&address) {
+			None => {
+				self.pending.insert(address, HandshakeState::AwaitingApproval { since: Instant::now() });
+				ConnectAction::AskApplication
+			}
+			Some(HandshakeState::AwaitingApproval { .. }) => ConnectAction::Ignore,
+			Some(HandshakeState::ResponseSent { .. }) => ConnectAction::ResendResponse,
+		}
+	}
+	
+	//Returns false, if there is no handshake with this remote (anymore):
+	pub fn approve(&mut self, address: &SocketAddr) -> bool {
+		let state = match self.pending.get_mut(address) {
+			Some(state) => state,
+			None => return false,
+		};
+		let sent_count = match state {
+			HandshakeState::AwaitingApproval { .. } => 1,
+			HandshakeState::ResponseSent { sent_count, .. } => *sent_count + 1,
+		};
+		*state = HandshakeState::ResponseSent { last_sent: Instant::now(), sent_count };
+		true
+	}
+	
+	//Ends the handshake, when rejected or the remote gave up. Returns false, if there was none:
+	pub fn cancel(&mut self, address: &SocketAddr) -> bool {
+		self.pending.remove(address).is_some()
+	}
+	
+	//Completes the handshake, returns false if the remote never got approved:
+	pub fn establish(&mut self, address: &SocketAddr) -> bool {
+		match self.pending.get(address) {
+			Some(HandshakeState::ResponseSent { .. }) => {
+				self.pending.remove(address);
+				true
+			}
+			_ => false,
+		}
+	}
+	
+	//Updates resend state and drops timed out handshakes.
+	//Returns the remotes, that need the response to be resent, and the ones that timed out:
+	pub fn due_resends(&mut self, settings: &HandshakeSettings) -> (Vec<SocketAddr>, Vec<SocketAddr>) {
+		let now = Instant::now();
+		let mut to_resend = Vec::new();
+		let mut timed_out = Vec::new();
+		self.pending.retain(|address, state| {
+			match state {
+				HandshakeState::AwaitingApproval { since } => {
+					if now - *since < settings.approval_timeout {
+						return true;
+					}
+					log_warn!("Handshake with ", address.ip(), ":", address.port(), " timed out, the connect was never approved or rejected.");
+					timed_out.push(*address);
+					false
+				}
+				HandshakeState::ResponseSent { last_sent, sent_count } => {
+					if now - *last_sent < settings.resend_interval {
+						return true;
+					}
+					if *sent_count >= settings.max_response_attempts {
+						log_warn!("Handshake with ", address.ip(), ":", address.port(), " timed out, the connect response was never confirmed.");
+						timed_out.push(*address);
+						return false;
+					}
+					*last_sent = now;
+					*sent_count += 1;
+					to_resend.push(*address);
+					true
+				}
+			}
+		});
+		(to_resend, timed_out)
+	}
+	
+	pub fn next_deadline(&self, settings: &HandshakeSettings) -> Option<Instant> {
+		self.pending.values().map(|state| match state {
+			HandshakeState::AwaitingApproval { since } => *since + settings.approval_timeout,
+			HandshakeState::ResponseSent { last_sent, .. } => *last_sent + settings.resend_interval,
+		}).min()
+	}
+}
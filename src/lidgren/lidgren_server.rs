@@ -1,8 +1,7 @@
 use crate::prelude::*;
 
 use std::collections::HashMap;
-use std::io::ErrorKind::WouldBlock;
-use std::net::{SocketAddr, UdpSocket};
+use std::net::SocketAddr;
 use std::ops::Add;
 use std::time::{Duration, Instant};
 
@@ -12,48 +11,65 @@ use crate::lidgren::message_type::MessageType;
 use crate::lidgren::connected_client::ConnectedClient;
 use crate::lidgren::data_types::DataType;
 use crate::lidgren::util::formatter as lg_formatter;
+use crate::lidgren::mtu_discovery::PROTOCOL_MAX_MTU;
+use crate::lidgren::transport::{Transport, UdpTransport, Waker};
+use crate::lidgren::connection_statistics::ConnectionStatistics;
+use crate::lidgren::send_priority::SendPriority;
+use crate::lidgren::flood_protection::{FloodProtection, FloodProtectionSettings, UnconnectedTraffic};
+use crate::lidgren::handshake::{ConnectAction, Handshakes, HandshakeSettings};
 
 pub struct ServerInstance {
 	application_name: String,
 	server_unique_id: u64,
-	socket: SocketWrapper,
+	transport: Box<dyn Transport>,
 	input_buffer: [u8; 0xFFFF],
 	user_map: HashMap<SocketAddr, ConnectedClient>,
+	handshakes: Handshakes,
 	time_run_duration: Instant,
 	time_cleanup: Instant,
 	pub new_data_packets: Vec<DataPacket>,
+	pub connection_settings: ConnectionSettings,
+	pub flood_protection: FloodProtection,
 }
 
-pub struct SocketWrapper {
-	socket: UdpSocket,
+//Connections are checked for timeouts and unfinished fragments this often:
+const DURATION_BETWEEN_CLEANUPS: Duration = Duration::from_millis(500);
+
+pub struct ConnectionSettings {
+	//Connections that did not send anything for this long are dropped:
+	pub inactivity_timeout: Duration,
+	//Connections that did not acknowledge a message after it got sent this many times are dropped:
+	pub max_resend_count: u32,
+	//Probe for bigger datagrams than the default MTU:
+	pub mtu_discovery: bool,
+	//Upper limit for MTU discovery. The socket does not prevent IP fragmentation, so probes may succeed,
+	// while the datagrams get fragmented on the way. Only raise this on networks with bigger frames.
+	pub max_mtu: usize,
+	//Fragmented messages are reassembled in memory, these limits prevent a remote from making the server allocate without bound.
+	//Byte sum of all unfinished fragmented messages of a connection:
+	pub max_fragment_bytes: usize,
+	//Amount of fragmented messages of a connection, that may be unfinished at the same time:
+	pub max_fragment_groups: usize,
+	//Biggest chunk size a fragment may announce, no datagram can carry more:
+	pub max_fragment_chunk_size: u32,
+	//Bytes per second sent to each client, None for no limit. Higher priority messages get the budget first:
+	pub max_bytes_per_second: Option<usize>,
+	//Timeouts and resends of the connect response, before a remote is connected:
+	pub handshake: HandshakeSettings,
 }
 
-impl SocketWrapper {
-	pub fn send(&self, data: &[u8], address: &SocketAddr) {
-		match self.socket.send_to(data, address) {
-			Ok(number_bytes) => {
-				if number_bytes != data.len() {
-					log_error!("ERROR: Failed to send right amount of bytes via socket ", number_bytes, " / ", data.len());
-				}
-			}
-			Err(e) => {
-				panic!("Unexpected error while sending via socket {:?}", e);
-			}
-		}
-	}
-	
-	fn receive(&mut self, input_buffer: &mut [u8]) -> Option<(usize, SocketAddr)> {
-		match self.socket.recv_from(input_buffer) {
-			Err(err) if err.kind() == WouldBlock => {
-				None //No packet in buffer right now, skip reading.
-			}
-			Err(err) => {
-				log_error!("Error while reading from socket: ", format!("{:?}", err));
-				None //We got an error, so stop reading
-			}
-			Ok(value) => {
-				Some(value) //All good, forward packet!
-			}
+impl Default for ConnectionSettings {
+	fn default() -> Self {
+		Self {
+			inactivity_timeout: Duration::from_secs(25), //Same as Lidgren's default.
+			max_resend_count: 30,
+			mtu_discovery: true,
+			max_mtu: 1452, //Ethernet frame, minus IPv6 and UDP header.
+			max_fragment_bytes: 16 * 1024 * 1024,
+			max_fragment_groups: 32,
+			max_fragment_chunk_size: PROTOCOL_MAX_MTU as u32,
+			max_bytes_per_second: Some(2 * 1024 * 1024),
+			handshake: HandshakeSettings::default(),
 		}
 	}
 }
@@ -76,50 +92,98 @@ impl ServerInstance {
 		server_unique_id: u64,
 		target: String,
 	) -> EhResult<ServerInstance> {
-		let socket = UdpSocket::bind(target).map_ex(ex!("While binding server socket"))?;
-		socket.set_nonblocking(true).map_ex(ex!("While setting socket to non-blocking mode"))?;
-		
+		let transport = UdpTransport::bind(target)?;
+		Ok(ServerInstance::with_transport(application_name, server_unique_id, Box::new(transport)))
+	}
+	
+	//Runs the server on any transport, like the in-memory loopback one:
+	pub fn with_transport(
+		application_name: String,
+		server_unique_id: u64,
+		transport: Box<dyn Transport>,
+	) -> ServerInstance {
 		let input_buffer: [u8; 0xFFFF] = [0; 0xFFFF];
 		let now = Instant::now();
 		
-		Ok(ServerInstance {
-			socket: SocketWrapper { socket },
+		ServerInstance {
+			transport,
 			input_buffer,
 			application_name,
 			server_unique_id,
 			user_map: HashMap::new(),
+			handshakes: Handshakes::default(),
 			time_run_duration: now,
 			time_cleanup: now,
 			new_data_packets: Vec::new(),
-		})
+			connection_settings: ConnectionSettings::default(),
+			flood_protection: FloodProtection::new(FloodProtectionSettings::default()),
+		}
 	}
 	
 	pub fn heartbeat(&mut self) {
-		let duration_between_cleanups = Duration::from_millis(500);
-		if self.time_cleanup.elapsed().ge(&duration_between_cleanups) {
+		if self.time_cleanup.elapsed().ge(&DURATION_BETWEEN_CLEANUPS) {
+			let mut to_disconnect = Vec::new();
 			for client in self.user_map.values_mut() {
-				client.heartbeat();
+				if let Some((event_type, reason)) = client.heartbeat(&self.connection_settings) {
+					to_disconnect.push((client.remote_address, event_type, reason));
+				}
 			}
-			self.time_cleanup = self.time_cleanup.add(duration_between_cleanups);
+			for (address, event_type, reason) in to_disconnect {
+				self.drop_connection(address, event_type, &reason);
+			}
+			self.flood_protection.cleanup();
+			//After waiting for a long time, there is no point in catching up with every missed cleanup:
+			self.time_cleanup = self.time_cleanup.add(DURATION_BETWEEN_CLEANUPS).max(Instant::now() - DURATION_BETWEEN_CLEANUPS);
+		}
+		
+		//Resend connect responses, that got no confirmation:
+		let (to_resend, timed_out) = self.handshakes.due_resends(&self.connection_settings.handshake);
+		for address in to_resend {
+			log_debug!("Resending connect response to ", address.ip(), ":", address.port());
+			self.send_connect_response(&address);
+		}
+		for address in timed_out {
+			ServerInstance::push_lifecycle_event(&mut self.new_data_packets, address, DataType::TimedOut, "Connection handshake timed out");
 		}
 		
 		//Send messages:
 		for client in self.user_map.values_mut() {
-			client.send_messages(&mut self.socket);
+			client.send_messages(self.transport.as_ref());
 		}
 		
 		let start = Instant::now();
-		let max_read_duration = Duration::from_millis(100);
-		//Read packets until at max 100ms have passed, then the rest of the program should continue (to consume the new packets).
+		let max_read_duration = Duration::from_millis(10);
+		//Read packets until at max 10ms have passed, then the new packets get handed over and acknowledges sent.
 		while start.elapsed().lt(&max_read_duration)
 		{
-			match self.socket.receive(&mut self.input_buffer) {
+			match self.transport.receive(&mut self.input_buffer) {
 				None => break,
 				Some((amount_read, remote_address)) => self.process_packet(amount_read, remote_address),
 			}
 		}
 	}
 	
+	//The next time 'heartbeat' has something to do, besides reading packets. None if there are no connections or handshakes:
+	pub fn next_deadline(&self) -> Option<Instant> {
+		let handshake_deadline = self.handshakes.next_deadline(&self.connection_settings.handshake);
+		let client_deadline = self.user_map.values().map(|client| client.next_deadline()).min()
+			.map(|deadline| deadline.min(self.time_cleanup + DURATION_BETWEEN_CLEANUPS));
+		match (handshake_deadline, client_deadline) {
+			(Some(handshake_deadline), Some(client_deadline)) => Some(handshake_deadline.min(client_deadline)),
+			(handshake_deadline, client_deadline) => handshake_deadline.or(client_deadline),
+		}
+	}
+	
+	//Sleeps until a packet arrives, the next deadline is reached or the waker got called:
+	pub fn wait_for_work(&mut self) {
+		let timeout = self.next_deadline().map(|deadline| deadline.saturating_duration_since(Instant::now()));
+		self.transport.wait_readable(timeout);
+	}
+	
+	pub fn waker(&self) -> Waker {
+		self.transport.waker()
+	}
+	
 	pub fn send_to(&mut self, address: SocketAddr, data: Vec<u8>) {
 		let connected_client = self.user_map.get_mut(&address).unwrap_or_else(|| {
 			panic!("The user, which this packet was about to be sent to, does not exist... Highly suspicious.");
@@ -127,101 +191,224 @@ impl ServerInstance {
 		connected_client.send_to(data);
 	}
 	
+	pub fn send_with_priority(&mut self, address: SocketAddr, priority: SendPriority, data: Vec<u8>) {
+		self.send_via(address, priority.message_type(), data);
+	}
+	
+	pub fn send_via(&mut self, address: SocketAddr, message_type: MessageType, data: Vec<u8>) {
+		let connected_client = self.user_map.get_mut(&address).unwrap_or_else(|| {
+			panic!("The user, which this packet was about to be sent to, does not exist... Highly suspicious.");
+		});
+		connected_client.send_via(message_type, data);
+	}
+	
+	pub fn is_connected(&self, address: &SocketAddr) -> bool {
+		self.user_map.contains_key(address)
+	}
+	
+	pub fn connection_statistics(&self, address: &SocketAddr) -> Option<ConnectionStatistics> {
+		self.user_map.get(address).map(|connected_client| connected_client.statistics())
+	}
+	
+	//Statistics of every connection, to spot the ones that lag or use the most bandwidth:
+	pub fn all_connection_statistics(&self) -> Vec<(SocketAddr, ConnectionStatistics)> {
+		self.user_map.iter().map(|(address, connected_client)| (*address, connected_client.statistics())).collect()
+	}
+	
+	//Kicks the connection on the server side, the remote gets told why:
+	pub fn disconnect(&mut self, address: SocketAddr, reason: &str) {
+		self.drop_connection(address, DataType::Kicked, reason);
+	}
+	
+	fn drop_connection(&mut self, address: SocketAddr, event_type: DataType, reason: &str) {
+		if self.user_map.remove(&address).is_none() {
+			log_warn!("Attempted to disconnect ", address.ip(), ":", address.port(), ", but it is not connected.");
+			return;
+		}
+		log_warn!(">> Disconnecting client ", address.ip(), ":", address.port(), " with reason: '", reason, "'");
+		self.send_disconnect(&address, reason);
+		ServerInstance::push_lifecycle_event(&mut self.new_data_packets, address, event_type, reason);
+	}
+	
+	//Refuses a remote that is not yet connected, the client shows the reason:
+	pub fn reject_connect(&mut self, remote_address: &SocketAddr, reason: &str) {
+		//The handshake might have completed meanwhile, then the connection gets dropped instead:
+		if self.is_connected(remote_address) {
+			self.disconnect(*remote_address, reason);
+			return;
+		}
+		self.handshakes.cancel(remote_address);
+		log_info!(">> Rejecting connection of ", remote_address.ip(), ":", remote_address.port(), " with reason: '", reason, "'");
+		self.send_disconnect(remote_address, reason);
+	}
+	
+	fn send_disconnect(&self, address: &SocketAddr, reason: &str) {
+		let mut result_buffer = vec!(
+			MessageType::Disconnect.to_index(),
+			0, 0, 0, 0,
+		);
+		lg_formatter::write_string(&mut result_buffer, reason);
+		let size = (result_buffer.len() - 5) * 8;
+		result_buffer[3] = size as u8;
+		result_buffer[4] = (size >> 8) as u8;
+		self.transport.send(&result_buffer, address);
+	}
+	
+	//Whatever the client sent is no longer relevant once it is gone, the event replaces it:
+	fn push_lifecycle_event(new_data_packets: &mut Vec<DataPacket>, remote_address: SocketAddr, event_type: DataType, reason: &str) {
+		if event_type != DataType::Connected {
+			new_data_packets.retain(|packet| packet.data_type != DataType::Data || packet.remote_address != remote_address);
+		}
+		new_data_packets.push(DataPacket {
+			data_type: event_type,
+			remote_address,
+			data: reason.as_bytes().to_vec(),
+		});
+	}
+	
 	pub fn process_packet(&mut self, amount_read: usize, remote_address: SocketAddr) {
 		log_debug!("====================================");
 		log_debug!("Received UDP packet from ", remote_address.ip(), " port ", remote_address.port(), " size ", amount_read);
 		
+		let connected = self.user_map.contains_key(&remote_address);
+		if !connected && self.flood_protection.is_blocked(&remote_address.ip()) {
+			log_debug!("Dropping packet, the remote is blocked.");
+			return;
+		}
+		if !self.parse_packet(amount_read, remote_address) && !connected {
+			self.flood_protection.allow(remote_address.ip(), UnconnectedTraffic::Malformed);
+		}
+	}
+	
+	//Returns false, if the packet turned out to be malformed:
+	fn parse_packet(&mut self, amount_read: usize, remote_address: SocketAddr) -> bool {
 		if amount_read < MESSAGE_HEADER_LENGTH {
 			//Drop packet, it cannot even hold a single Lidgren message header.
 			log_warn!("Dropping packet, message header won't fit inside.");
-			return;
+			return false;
+		}
+		
+		if let Some(connected_client) = self.user_map.get_mut(&remote_address) {
+			connected_client.received_datagram(amount_read);
 		}
 		
 		let mut iterator = CustomIterator::borrow(&self.input_buffer[0..amount_read]);
+		//Set once the client has to be kicked, the rest of the packet is not parsed anymore:
+		let mut kick_reason = None;
 		
 		while iterator.remaining() >= MESSAGE_HEADER_LENGTH {
-			let header = unwrap_or_print_return!(MessageHeader::from_stream(&mut iterator).wrap(ex!("While constructing lidgren header")));
+			let header = unwrap_or_print_return!(MessageHeader::from_stream(&mut iterator).wrap(ex!("While constructing lidgren header")), false);
 			log_debug!("Type: ", format!("{:x?}", header.message_type), " Fragment: ", header.fragment, " Sequence#: ", header.sequence_number, " Bits: ", header.bits, " Bytes: ", header.bytes);
+			if let Some(connected_client) = self.user_map.get_mut(&remote_address) {
+				connected_client.received_message();
+			}
 			
 			if (iterator.remaining() as u16) < header.bytes {
 				log_warn!("Message header declared payload size bigger than rest of packet: ", header.bytes, "/", iterator.remaining());
-				return;
+				return false;
 			}
 			
 			if let MessageType::Unused(_) = header.message_type {
 				log_warn!("Received Unused/Reserved message type. Stopping parsing.");
-				return;
+				return false;
 			}
 			
-			let message_data_iterator = unwrap_or_print_return!(iterator.sub_section(header.bytes as usize).wrap(ex!("While creating message-sub-iterator")));
+			let message_data_iterator = unwrap_or_print_return!(iterator.sub_section(header.bytes as usize).wrap(ex!("While creating message-sub-iterator")), false);
 			
 			if MessageType::is_system(&header.message_type) {
 				match header.message_type {
-					MessageType::Discovery =>
-						ServerInstance::handle_packet_discovery(message_data_iterator, remote_address, &mut self.new_data_packets),
-					MessageType::Connect =>
-						ServerInstance::handle_packet_connect(message_data_iterator, remote_address, &mut self.new_data_packets, &self.application_name),
-					MessageType::ConnectionEstablished =>
-						ServerInstance::handle_packet_connection_established(message_data_iterator, remote_address, &mut self.user_map),
+					MessageType::Discovery => {
+						if !self.flood_protection.allow(remote_address.ip(), UnconnectedTraffic::Discovery) {
+							return true;
+						}
+						ServerInstance::handle_packet_discovery(message_data_iterator, remote_address, &mut self.new_data_packets);
+					}
+					MessageType::Connect => {
+						if !self.flood_protection.allow(remote_address.ip(), UnconnectedTraffic::Connect) {
+							return true;
+						}
+						let data = unwrap_or_return!(ServerInstance::handle_packet_connect(message_data_iterator, remote_address, &self.application_name), false);
+						if self.user_map.contains_key(&remote_address) {
+							log_debug!("Connected remote ", remote_address.ip(), ":", remote_address.port(), " sent connect again, ignoring.");
+							continue;
+						}
+						match self.handshakes.received_connect(remote_address) {
+							ConnectAction::AskApplication => self.new_data_packets.push(DataPacket {
+								data_type: DataType::Connect,
+								remote_address,
+								data,
+							}),
+							//The response got lost, the application already approved:
+							ConnectAction::ResendResponse => self.send_connect_response(&remote_address),
+							ConnectAction::Ignore => {}
+						}
+					}
+					MessageType::ConnectionEstablished => {
+						if !ServerInstance::handle_packet_connection_established(message_data_iterator, remote_address, &mut self.new_data_packets, &mut self.user_map, &mut self.handshakes, &self.connection_settings) {
+							return false;
+						}
+					}
 					MessageType::Ping =>
-						ServerInstance::handle_packet_ping(message_data_iterator, remote_address, &self.time_run_duration, &self.socket),
+						ServerInstance::handle_packet_ping(message_data_iterator, remote_address, &self.time_run_duration, self.transport.as_ref()),
+					MessageType::Pong =>
+						ServerInstance::handle_packet_pong(message_data_iterator, remote_address, &mut self.user_map),
 					MessageType::Disconnect =>
-						ServerInstance::handle_packet_disconnect(message_data_iterator, remote_address, &mut self.new_data_packets, &mut self.user_map),
+						ServerInstance::handle_packet_disconnect(message_data_iterator, remote_address, &mut self.new_data_packets, &mut self.user_map, &mut self.handshakes),
 					MessageType::Acknowledge =>
 						ServerInstance::handle_packet_acknowledged(message_data_iterator, remote_address, &mut self.user_map),
+					MessageType::ExpandMTURequest =>
+						ServerInstance::handle_packet_expand_mtu_request(message_data_iterator, remote_address, &self.user_map, self.transport.as_ref()),
+					MessageType::ExpandMTUSuccess =>
+						ServerInstance::handle_packet_expand_mtu_success(message_data_iterator, remote_address, &mut self.user_map),
 					_ => {
 						//Reject!
 						log_warn!("Rejecting message type ", format!("{:?}", header.message_type), " from ", remote_address.ip(), ":", remote_address.port(), " remaining bytes ", message_data_iterator.remaining());
-						return;
+						return false;
 					}
 				}
 			} else {
-				//Get channel:
 				match header.message_type {
-					MessageType::UserReliableOrdered(channel) => {
-						//Acknowledge:
-						{
-							//TODO: Do acknowledge somewhere else? (And collected)
-							let mut result_buffer = Vec::new();
-							result_buffer.push(MessageType::Acknowledge.to_index());
-							result_buffer.push(0);
-							result_buffer.push(0);
-							let length = 1 * 3 * 8;
-							result_buffer.push(length as u8);
-							result_buffer.push((length >> 8) as u8);
-							
-							result_buffer.push(header.message_type.to_index());
-							result_buffer.push(header.sequence_number as u8);
-							result_buffer.push((header.sequence_number >> 8) as u8);
-							
-							self.socket.send(&result_buffer, &remote_address);
-						}
-						//Handle:
-						if channel != 0 {
-							log_warn!("Cannot handle anything but channel 0 yet!");
-							return;
-						}
+					MessageType::UserUnreliable
+					| MessageType::UserSequenced(_)
+					| MessageType::UserReliableUnordered
+					| MessageType::UserReliableSequenced(_)
+					| MessageType::UserReliableOrdered(_) => {
+						//Handle (and queue acknowledge):
 						let connected_client = self.user_map.get_mut(&remote_address);
 						let connected_client = unwrap_or_return!(connected_client, {
 							log_warn!("Client sent user-message, while not being connected!");
+							false
 						});
-						connected_client.handle_new_message(
+						kick_reason = connected_client.handle_new_message(
 							&mut self.new_data_packets,
 							remote_address,
 							header,
 							message_data_iterator,
+							&self.connection_settings,
 						);
+						if kick_reason.is_some() {
+							break;
+						}
 					}
 					_ => {
 						log_warn!("Unexpected/Unimplemented message type!");
-						return;
+						return false;
 					}
 				};
 			}
 		}
-		if iterator.remaining() > 0 {
-			log_warn!("Dropping packet, there had been additional bytes to read that don't fit a message header. Amount ", iterator.remaining());
+		let remaining = iterator.remaining();
+		drop(iterator); //Releases the input buffer, for the disconnect to borrow the server.
+		if let Some(reason) = kick_reason {
+			log_warn!("Kicking ", remote_address.ip(), ":", remote_address.port(), " reason: ", reason);
+			self.disconnect(remote_address, &reason);
+			return true;
 		}
+		if remaining > 0 {
+			log_warn!("Dropping packet, there had been additional bytes to read that don't fit a message header. Amount ", remaining);
+			return false;
+		}
+		true
 	}
 	
 	fn handle_packet_discovery(mut iterator: CustomIterator, remote_address: SocketAddr, new_data_packets: &mut Vec<DataPacket>) {
@@ -233,35 +420,44 @@ impl ServerInstance {
 		});
 	}
 	
-	fn handle_packet_connect(mut iterator: CustomIterator, remote_address: SocketAddr, new_data_packets: &mut Vec<DataPacket>, application_name: &String) {
-		let app_id = unwrap_or_print_return!(lg_formatter::read_string(&mut iterator).wrap(ex!("While reading app id, in connect message")));
+	//Returns the payload for the application, None if the message is malformed:
+	fn handle_packet_connect(mut iterator: CustomIterator, remote_address: SocketAddr, application_name: &String) -> Option<Vec<u8>> {
+		let app_id = unwrap_or_print_return!(lg_formatter::read_string(&mut iterator).wrap(ex!("While reading app id, in connect message")), None);
 		if application_name.ne(&app_id) {
 			log_warn!("Remote ", remote_address.ip(), ":", remote_address.port(), " sent wrong application identifier name '", app_id, "'.");
-			return;
+			return None;
 		}
 		//TODO: Actually somehow use the ID? Only useful if routers actually do funky stuff...
 		let _remote_id = lg_formatter::read_int_64(&mut iterator);
-		let remote_time = unwrap_or_print_return!(lg_formatter::read_float(&mut iterator).wrap(ex!("While reading the remote time")));
+		let remote_time = unwrap_or_print_return!(lg_formatter::read_float(&mut iterator).wrap(ex!("While reading the remote time")), None);
 		log_debug!("Remote time: ", remote_time);
-		new_data_packets.push(DataPacket {
-			data_type: DataType::Connect,
-			remote_address,
-			data: iterator.consume(),
-		});
+		Some(iterator.consume())
 	}
 	
-	fn handle_packet_connection_established(mut iterator: CustomIterator, remote_address: SocketAddr, user_map: &mut HashMap<SocketAddr, ConnectedClient>) {
+	//Returns false, if the message is malformed or unexpected:
+	fn handle_packet_connection_established(mut iterator: CustomIterator, remote_address: SocketAddr, new_data_packets: &mut Vec<DataPacket>, user_map: &mut HashMap<SocketAddr, ConnectedClient>, handshakes: &mut Handshakes, settings: &ConnectionSettings) -> bool {
 		if iterator.remaining() != 4 {
 			log_warn!("Remote ", remote_address.ip(), ":", remote_address.port(), " sent invalid connection established message, expected exactly 4 bytes, got ", iterator.remaining());
-			return;
+			return false;
 		}
-		let remote_time = unwrap_or_print_return!(lg_formatter::read_float(&mut iterator).wrap(ex!("While reading the remote time")));
+		let remote_time = unwrap_or_print_return!(lg_formatter::read_float(&mut iterator).wrap(ex!("While reading the remote time")), false);
 		log_debug!("Remote time: ", remote_time);
+		if user_map.contains_key(&remote_address) {
+			//The client answers every resent connect response, the first one already got through:
+			log_debug!("Remote ", remote_address.ip(), ":", remote_address.port(), " confirmed the connection again, ignoring.");
+			return true;
+		}
+		if !handshakes.establish(&remote_address) {
+			log_warn!("Remote ", remote_address.ip(), ":", remote_address.port(), " sent connection established, without being approved.");
+			return false;
+		}
 		//Register user:
-		user_map.insert(remote_address, ConnectedClient::new(remote_address));
+		user_map.insert(remote_address, ConnectedClient::new(remote_address, settings));
+		ServerInstance::push_lifecycle_event(new_data_packets, remote_address, DataType::Connected, "");
+		true
 	}
 	
-	fn handle_packet_ping(mut iterator: CustomIterator, remote_address: SocketAddr, time_run_duration: &Instant, socket: &SocketWrapper) {
+	fn handle_packet_ping(mut iterator: CustomIterator, remote_address: SocketAddr, time_run_duration: &Instant, transport: &dyn Transport) {
 		if iterator.remaining() != 1 {
 			log_warn!("Remote ", remote_address.ip(), ":", remote_address.port(), " sent invalid ping message, expected exactly 1 byte, got ", iterator.remaining());
 			return;
@@ -287,33 +483,74 @@ impl ServerInstance {
 			result_buffer[3] = size as u8;
 			result_buffer[4] = (size >> 8) as u8;
 			
-			socket.send(&result_buffer, &remote_address);
+			transport.send(&result_buffer, &remote_address);
 		}
 	}
 	
-	fn handle_packet_disconnect(mut iterator: CustomIterator, remote_address: SocketAddr, new_data_packets: &mut Vec<DataPacket>, user_map: &mut HashMap<SocketAddr, ConnectedClient>) {
+	fn handle_packet_pong(mut iterator: CustomIterator, remote_address: SocketAddr, user_map: &mut HashMap<SocketAddr, ConnectedClient>) {
+		if iterator.remaining() != 5 {
+			log_warn!("Remote ", remote_address.ip(), ":", remote_address.port(), " sent invalid pong message, expected exactly 5 bytes, got ", iterator.remaining());
+			return;
+		}
+		let connected_client = unwrap_or_return!(user_map.get_mut(&remote_address), {
+			log_warn!("Warning: Unconnected user sent pong packet - ignoring!");
+		});
+		let ping_number = iterator.next_unchecked();
+		//The remote time is not needed, the round trip time is measured locally.
+		log_debug!("Pong packet: ", ping_number);
+		connected_client.received_pong(ping_number);
+	}
+	
+	fn handle_packet_disconnect(mut iterator: CustomIterator, remote_address: SocketAddr, new_data_packets: &mut Vec<DataPacket>, user_map: &mut HashMap<SocketAddr, ConnectedClient>, handshakes: &mut Handshakes) {
+		if handshakes.cancel(&remote_address) {
+			log_debug!("Remote ", remote_address.ip(), ":", remote_address.port(), " gave up connecting.");
+			let reason = lg_formatter::read_string(&mut iterator).unwrap_or_default();
+			ServerInstance::push_lifecycle_event(new_data_packets, remote_address, DataType::Disconnected, &reason);
+			return;
+		}
 		//First disconnect the client (as in stop sending it data and clean up):
-		//TODO: Maybe improve external disconnection...
-		user_map.remove(&remote_address); //Brute force way to get rid of it. Deal with the aftermath later...
-		let mut i = 0;
-		while i < new_data_packets.len() {
-			let packet: &DataPacket = new_data_packets.get(i).unwrap(); //As per condition above, we are still in bounds.
-			if packet.data_type == DataType::Data && packet.remote_address == remote_address {
-				new_data_packets.remove(i);
-			} else {
-				i += 1;
-			}
+		if user_map.remove(&remote_address).is_none() {
+			log_warn!("Warning: Unconnected user sent disconnect packet - ignoring!");
+			return;
 		}
 		//Now read the actual packet content:
-		let disconnection_reason = unwrap_or_print_return!(lg_formatter::read_string(&mut iterator).wrap(ex!("While reading disconnect reason")));
+		let disconnection_reason = match lg_formatter::read_string(&mut iterator).wrap(ex!("While reading disconnect reason")) {
+			Ok(reason) => reason,
+			Err(error) => {
+				//The client is gone either way, the application still has to know about it.
+				error.print();
+				String::new()
+			}
+		};
 		log_warn!(">> Client disconnected with reason: '", disconnection_reason, "'");
 		if iterator.has_more() {
 			log_warn!("Warning Disconnect packet had more data to read: ", iterator.remaining(), " bytes");
 		}
 		
-		//TODO: Confirm that removing the packets actually worked...
-		log_debug!("Destroyed user data and (hopefully) purged all incoming packets by it.");
-		//TODO: Let main application know about this (send pseudo packet).
+		//Purges all incoming packets by it and lets the application know:
+		ServerInstance::push_lifecycle_event(new_data_packets, remote_address, DataType::Disconnected, &disconnection_reason);
+	}
+	
+	fn handle_packet_expand_mtu_request(iterator: CustomIterator, remote_address: SocketAddr, user_map: &HashMap<SocketAddr, ConnectedClient>, transport: &dyn Transport) {
+		if !user_map.contains_key(&remote_address) {
+			log_warn!("Warning: Unconnected user sent expand MTU request - ignoring!");
+			return;
+		}
+		//The probe arrived, tell the remote how big it was:
+		let payload_size = iterator.remaining();
+		log_debug!("[MTU] Remote probe with payload of ", payload_size, " bytes arrived.");
+		let mut result_buffer = Vec::with_capacity(5 + 4);
+		MessageHeader::write(&mut result_buffer, &MessageType::ExpandMTUSuccess, false, 0, 4);
+		lg_formatter::write_int_32(&mut result_buffer, payload_size as u32);
+		transport.send(&result_buffer, &remote_address);
+	}
+	
+	fn handle_packet_expand_mtu_success(mut iterator: CustomIterator, remote_address: SocketAddr, user_map: &mut HashMap<SocketAddr, ConnectedClient>) {
+		let connected_client = unwrap_or_return!(user_map.get_mut(&remote_address), {
+			log_warn!("Warning: Unconnected user sent expand MTU success - ignoring!");
+		});
+		let payload_size = unwrap_or_print_return!(lg_formatter::read_int_32(&mut iterator).wrap(ex!("While reading expand MTU success size")));
+		connected_client.received_mtu_success(payload_size as usize);
 	}
 	
 	fn handle_packet_acknowledged(
@@ -337,16 +574,23 @@ impl ServerInstance {
 				log_warn!("Warning: Connected user sent invalid acknowledge packet: Invalid message type id ", raw_id);
 			});
 			let sequence_number = iterator.next_unchecked() as u16 | ((iterator.next_unchecked() as u16) << 8);
-			if MessageType::UserReliableOrdered(0) != message_type {
+			log_debug!("Received acknowledge for ", format!("{:?}", message_type), " sequence id ", sequence_number);
+			if !connected_client.received_acknowledge(&message_type, sequence_number) {
 				log_warn!("Warning: Connected user sent invalid acknowledge packet: Message type, that we most certainly never sent ", format!("{:?}", message_type), " with sequence number ", sequence_number);
-				continue;
 			}
-			log_debug!("Received acknowledge for sequence id ", sequence_number);
-			connected_client.received_acknowledge(sequence_number);
 		}
 	}
 	
-	pub fn answer_connect(&self, remote_address: &SocketAddr) {
+	//Approves the connect, the remote may establish the connection now:
+	pub fn answer_connect(&mut self, remote_address: &SocketAddr) {
+		if !self.handshakes.approve(remote_address) {
+			log_warn!("Not answering connect of ", remote_address.ip(), ":", remote_address.port(), ", there is no handshake with it (anymore).");
+			return;
+		}
+		self.send_connect_response(remote_address);
+	}
+	
+	fn send_connect_response(&self, remote_address: &SocketAddr) {
 		let mut result_buffer = vec!(
 			MessageType::ConnectResponse.to_index(),
 			0, 0, 0, 0,
@@ -362,7 +606,7 @@ impl ServerInstance {
 		result_buffer[3] = size as u8;
 		result_buffer[4] = (size >> 8) as u8;
 		
-		self.socket.send(&result_buffer, remote_address);
+		self.transport.send(&result_buffer, remote_address);
 	}
 	
 	pub fn answer_discovery(&self, remote_address: &SocketAddr, discovery_payload: &[u8]) {
@@ -378,6 +622,6 @@ impl ServerInstance {
 		
 		result_buffer.extend_from_slice(discovery_payload);
 		
-		self.socket.send(&result_buffer, remote_address);
+		self.transport.send(&result_buffer, remote_address);
 	}
 }
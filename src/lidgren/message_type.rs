@@ -1,6 +1,9 @@
 use MessageType::*;
 
-#[derive(Debug, PartialEq, Eq)]
+//Amount of channels each channeled delivery method (sequenced, reliable sequenced & reliable ordered) has:
+pub const CHANNEL_COUNT: usize = 32;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum MessageType {
 	Unconnected,
 	UserUnreliable,
@@ -73,6 +76,12 @@ impl MessageType {
 			| Unused(_))
 	}
 	
+	pub const fn is_reliable(message_type: &MessageType) -> bool {
+		matches!(message_type, UserReliableUnordered
+			| UserReliableSequenced(_)
+			| UserReliableOrdered(_))
+	}
+	
 	pub fn to_index(&self) -> u8 {
 		match self {
 			Unconnected => 0,
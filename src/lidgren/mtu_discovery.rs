@@ -0,0 +1,90 @@
+use crate::prelude::*;
+
+use std::time::{Duration, Instant};
+use crate::lidgren::data_structures::DEFAULT_MTU;
+
+//Lidgren can not express bigger messages, as the bit length of a message has to fit 16 bits:
+pub const PROTOCOL_MAX_MTU: usize = 8190;
+//Once the range between working and failing probe size is this small, stop probing:
+const PROBE_PRECISION: usize = 8;
+
+//Finds the biggest datagram size, that reaches the remote - using Lidgren's ExpandMTU messages.
+//Binary searches between the last size that got confirmed and the smallest size that failed.
+pub struct MtuDiscovery {
+	mtu: usize,
+	smallest_failed: usize,
+	pending_probe: Option<(usize, Instant)>,
+	finished: bool,
+}
+
+impl MtuDiscovery {
+	pub fn new(enabled: bool, max_mtu: usize) -> Self {
+		let max_mtu = max_mtu.min(PROTOCOL_MAX_MTU);
+		Self {
+			mtu: DEFAULT_MTU,
+			smallest_failed: max_mtu + 1,
+			pending_probe: None,
+			finished: !enabled || max_mtu <= DEFAULT_MTU,
+		}
+	}
+	
+	pub fn mtu(&self) -> usize {
+		self.mtu
+	}
+	
+	//Returns the datagram size of the next probe, if one should be sent now:
+	pub fn next_probe(&mut self, timeout: Duration) -> Option<usize> {
+		if self.finished {
+			return None;
+		}
+		if let Some((size, sent)) = self.pending_probe {
+			if sent.elapsed().lt(&timeout) {
+				return None; //Still waiting for an answer.
+			}
+			//No answer, this size does not get through (or the probe got lost, then a bit of MTU is wasted):
+			log_debug!("[MTU] Probe with ", size, " bytes got no answer.");
+			self.smallest_failed = size;
+			self.pending_probe = None;
+		}
+		if self.smallest_failed - self.mtu <= PROBE_PRECISION {
+			self.finish();
+			return None;
+		}
+		let size = (self.mtu + self.smallest_failed) / 2;
+		self.pending_probe = Some((size, Instant::now()));
+		Some(size)
+	}
+	
+	//When 'next_probe' has to be called again, None once finished:
+	pub fn next_probe_time(&self, timeout: Duration) -> Option<Instant> {
+		if self.finished {
+			return None;
+		}
+		match self.pending_probe {
+			Some((_, sent)) => Some(sent + timeout),
+			None => Some(Instant::now()),
+		}
+	}
+	
+	pub fn probe_succeeded(&mut self, size: usize) {
+		if size <= self.mtu {
+			return; //Old or duplicated answer, nothing new.
+		}
+		if size >= self.smallest_failed {
+			log_warn!("[MTU] Remote confirmed probe size ", size, ", which was never sent.");
+			return;
+		}
+		log_debug!("[MTU] Probe with ", size, " bytes got through.");
+		self.mtu = size;
+		if let Some((pending_size, _)) = self.pending_probe {
+			if pending_size == size {
+				self.pending_probe = None;
+			}
+		}
+	}
+	
+	fn finish(&mut self) {
+		self.finished = true;
+		log_debug!("[MTU] Finished discovery, MTU is ", self.mtu, " bytes.");
+	}
+}
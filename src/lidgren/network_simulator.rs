@@ -0,0 +1,310 @@
+use crate::prelude::*;
+
+use std::cell::RefCell;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+use crate::lidgren::transport::{Transport, Waker};
+
+//How badly one direction of the simulated link behaves. The default is a perfect link.
+#[derive(Clone, Default)]
+pub struct Impairment {
+	//Chance (0.0 - 1.0) that a datagram gets lost:
+	pub loss: f32,
+	//Added to every datagram:
+	pub latency: Duration,
+	//Random extra delay between zero and this, datagrams overtake each other when it is big enough:
+	pub jitter: Duration,
+	//Chance that a datagram arrives twice:
+	pub duplication: f32,
+	//Chance that a datagram is held back by 'reorder_delay', so that the following ones overtake it:
+	pub reorder: f32,
+	pub reorder_delay: Duration,
+}
+
+impl Impairment {
+	//Reads comma separated settings, like "loss=0.1,latency=50,jitter=20". Durations are in milliseconds.
+	//Keys: loss, latency, jitter, duplication, reorder, reorder-delay
+	pub fn parse(text: &str) -> EhResult<Impairment> {
+		let mut impairment = Impairment::default();
+		for setting in text.split(',').map(str::trim).filter(|setting| !setting.is_empty()) {
+			let (key, value) = setting.split_once('=').map_ex(ex!("Expected 'key=value', got '", setting, "'"))?;
+			let chance = || -> EhResult<f32> {
+				let chance = value.parse::<f32>().map_ex(ex!("Expected a number for '", key, "', got '", value, "'"))?;
+				if !(0.0..=1.0).contains(&chance) {
+					return exception!("Chance '", key, "' has to be between 0.0 and 1.0, got ", chance);
+				}
+				Ok(chance)
+			};
+			let milliseconds = || -> EhResult<Duration> {
+				Ok(Duration::from_millis(value.parse::<u64>().map_ex(ex!("Expected milliseconds for '", key, "', got '", value, "'"))?))
+			};
+			match key {
+				"loss" => impairment.loss = chance()?,
+				"latency" => impairment.latency = milliseconds()?,
+				"jitter" => impairment.jitter = milliseconds()?,
+				"duplication" => impairment.duplication = chance()?,
+				"reorder" => impairment.reorder = chance()?,
+				"reorder-delay" => impairment.reorder_delay = milliseconds()?,
+				_ => return exception!("Unknown network simulation setting '", key, "'"),
+			}
+		}
+		Ok(impairment)
+	}
+}
+
+struct DelayedDatagram {
+	release_time: Instant,
+	address: SocketAddr,
+	data: Vec<u8>,
+}
+
+struct SimulatorState {
+	random: StdRng,
+	delayed_outgoing: Vec<DelayedDatagram>,
+	delayed_incoming: Vec<DelayedDatagram>,
+}
+
+//Wraps a transport and impairs the datagrams passing through it, to see how the server copes with bad links.
+//All random decisions come from a seeded generator, so the same seed and traffic gives the same losses.
+//Delayed datagrams are only released, when the server sends or receives, which it does every heartbeat.
+pub struct NetworkSimulator<T: Transport> {
+	inner: T,
+	pub outgoing: Impairment,
+	pub incoming: Impairment,
+	state: RefCell<SimulatorState>,
+	receive_buffer: Vec<u8>,
+}
+
+impl<T: Transport> NetworkSimulator<T> {
+	pub fn new(inner: T, seed: u64, outgoing: Impairment, incoming: Impairment) -> NetworkSimulator<T> {
+		NetworkSimulator {
+			inner,
+			outgoing,
+			incoming,
+			state: RefCell::new(SimulatorState {
+				random: StdRng::seed_from_u64(seed),
+				delayed_outgoing: Vec::new(),
+				delayed_incoming: Vec::new(),
+			}),
+			receive_buffer: vec![0; 0xFFFF],
+		}
+	}
+	
+	pub fn inner(&self) -> &T {
+		&self.inner
+	}
+	
+	fn release_outgoing(&self) {
+		let now = Instant::now();
+		let mut state = self.state.borrow_mut();
+		while let Some(datagram) = take_due(&mut state.delayed_outgoing, now) {
+			self.inner.send(&datagram.data, &datagram.address);
+		}
+	}
+}
+
+//Decides the fate of a datagram and queues every copy, that survives:
+fn impair(random: &mut StdRng, impairment: &Impairment, queue: &mut Vec<DelayedDatagram>, address: SocketAddr, data: &[u8]) {
+	if random.gen::<f32>() < impairment.loss {
+		log_debug!("[Simulator] Dropping datagram of ", data.len(), " bytes for ", address);
+		return;
+	}
+	let copies = if random.gen::<f32>() < impairment.duplication { 2 } else { 1 };
+	for _ in 0..copies {
+		let mut delay = impairment.latency;
+		if !impairment.jitter.is_zero() {
+			delay += impairment.jitter.mul_f32(random.gen::<f32>());
+		}
+		if random.gen::<f32>() < impairment.reorder {
+			delay += impairment.reorder_delay;
+		}
+		queue.push(DelayedDatagram {
+			release_time: Instant::now() + delay,
+			address,
+			data: data.to_vec(),
+		});
+	}
+}
+
+//Removes the datagram that is due first, equal times keep the order they got queued in:
+fn take_due(queue: &mut Vec<DelayedDatagram>, now: Instant) -> Option<DelayedDatagram> {
+	let mut due_index = None;
+	for (index, datagram) in queue.iter().enumerate() {
+		if datagram.release_time <= now && due_index.is_none_or(|due: usize| datagram.release_time < queue[due].release_time) {
+			due_index = Some(index);
+		}
+	}
+	due_index.map(|index| queue.remove(index))
+}
+
+impl<T: Transport> Transport for NetworkSimulator<T> {
+	fn send(&self, data: &[u8], address: &SocketAddr) {
+		{
+			let mut state = self.state.borrow_mut();
+			let state = &mut *state;
+			impair(&mut state.random, &self.outgoing, &mut state.delayed_outgoing, *address, data);
+		}
+		self.release_outgoing();
+	}
+	
+	fn receive(&mut self, input_buffer: &mut [u8]) -> Option<(usize, SocketAddr)> {
+		self.release_outgoing();
+		//Move everything the real transport has into the delay queue:
+		while let Some((amount, address)) = self.inner.receive(&mut self.receive_buffer) {
+			let state = self.state.get_mut();
+			impair(&mut state.random, &self.incoming, &mut state.delayed_incoming, address, &self.receive_buffer[..amount]);
+		}
+		let datagram = take_due(&mut self.state.get_mut().delayed_incoming, Instant::now())?;
+		let amount = datagram.data.len().min(input_buffer.len());
+		input_buffer[..amount].copy_from_slice(&datagram.data[..amount]);
+		Some((amount, datagram.address))
+	}
+	
+	fn wait_readable(&mut self, timeout: Option<Duration>) {
+		//Delayed datagrams have to be released in time, be it incoming or outgoing ones:
+		let now = Instant::now();
+		let state = self.state.get_mut();
+		let next_release = state.delayed_incoming.iter().chain(state.delayed_outgoing.iter())
+			.map(|datagram| datagram.release_time.saturating_duration_since(now))
+			.min();
+		let timeout = match (timeout, next_release) {
+			(Some(timeout), Some(next_release)) => Some(timeout.min(next_release)),
+			(timeout, next_release) => timeout.or(next_release),
+		};
+		if timeout.is_some_and(|timeout| timeout.is_zero()) {
+			return;
+		}
+		self.inner.wait_readable(timeout);
+	}
+	
+	fn waker(&self) -> Waker {
+		self.inner.waker()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	use crate::lidgren::data_types::DataType;
+	use crate::lidgren::lidgren_server::ServerInstance;
+	use crate::lidgren::test_client::{self, APPLICATION_NAME, TestClient, run_until};
+	use crate::lidgren::transport::LoopbackNetwork;
+	
+	//A bad link takes a few resends, with their backoff:
+	const TIMEOUT: Duration = Duration::from_secs(20);
+	
+	fn bad_link() -> Impairment {
+		Impairment {
+			loss: 0.2,
+			latency: Duration::ZERO,
+			jitter: Duration::from_millis(5),
+			duplication: 0.2,
+			reorder: 0.2,
+			reorder_delay: Duration::from_millis(20),
+		}
+	}
+	
+	//Only the server is behind the simulator, it impairs both directions:
+	fn start(seed: u64) -> (ServerInstance, TestClient) {
+		let network = LoopbackNetwork::new();
+		let server_address: SocketAddr = "10.0.0.1:43531".parse().unwrap();
+		let simulator = NetworkSimulator::new(network.bind(server_address), seed, bad_link(), bad_link());
+		let server = ServerInstance::with_transport(String::from(APPLICATION_NAME), 42, Box::new(simulator));
+		let client = TestClient::new(network.bind("10.0.0.2:50000".parse().unwrap()), server_address);
+		(server, client)
+	}
+	
+	#[test]
+	fn parse_settings() {
+		let impairment = Impairment::parse("loss=0.1, latency=50,jitter=20,duplication=0.05,reorder=0.2,reorder-delay=30").unwrap();
+		assert_eq!(impairment.loss, 0.1);
+		assert_eq!(impairment.latency, Duration::from_millis(50));
+		assert_eq!(impairment.jitter, Duration::from_millis(20));
+		assert_eq!(impairment.duplication, 0.05);
+		assert_eq!(impairment.reorder, 0.2);
+		assert_eq!(impairment.reorder_delay, Duration::from_millis(30));
+		assert!(Impairment::parse("loss=2").is_err());
+		assert!(Impairment::parse("latency=-5").is_err());
+		assert!(Impairment::parse("speed=1").is_err());
+		assert!(Impairment::parse("loss").is_err());
+	}
+	
+	#[test]
+	fn same_seed_gives_same_impairment() {
+		let run = |seed: u64| {
+			let network = LoopbackNetwork::new();
+			let receiver_address: SocketAddr = "10.0.0.2:50000".parse().unwrap();
+			let mut receiver = network.bind(receiver_address);
+			let impairment = Impairment {
+				loss: 0.3,
+				duplication: 0.3,
+				..Impairment::default()
+			};
+			let simulator = NetworkSimulator::new(network.bind("10.0.0.1:43531".parse().unwrap()), seed, impairment, Impairment::default());
+			for index in 0..100u8 {
+				simulator.send(&[index], &receiver_address);
+			}
+			let mut buffer = [0; 1];
+			let mut received = Vec::new();
+			while receiver.receive(&mut buffer).is_some() {
+				received.push(buffer[0]);
+			}
+			received
+		};
+		let received = run(1);
+		assert_eq!(received, run(1));
+		assert_ne!(received, run(2));
+		//Some got lost and some duplicated:
+		assert!((0..100).any(|index| !received.contains(&index)));
+		assert!(received.windows(2).any(|pair| pair[0] == pair[1]));
+	}
+	
+	#[test]
+	fn resends_deliver_fragments_over_bad_link() {
+		let (mut server, mut client) = start(11);
+		test_client::connect(&mut server, &mut client, TIMEOUT);
+		let address = client.address();
+		
+		//Bigger than the MTU, the chunks get lost, duplicated and reordered. Acknowledges too:
+		let data: Vec<u8> = (0..30000).map(|index| (index % 253) as u8).collect();
+		server.send_to(address, data.clone());
+		let mut received = Vec::new();
+		run_until(&mut server, &mut client, TIMEOUT, |_, client| {
+			received.extend(client.receive_data());
+			!received.is_empty()
+		});
+		assert_eq!(received, vec![data]);
+		assert!(server.connection_statistics(&address).unwrap().messages_resent > 0);
+	}
+	
+	#[test]
+	fn server_reassembles_fragments_over_bad_link() {
+		let (mut server, mut client) = start(7);
+		test_client::connect(&mut server, &mut client, TIMEOUT);
+		let address = client.address();
+		
+		let data: Vec<u8> = (0..20000).map(|index| (index % 251) as u8).collect();
+		client.send_fragmented(1, &data, 500);
+		let mut received = Vec::new();
+		let mut last_resend = Instant::now();
+		run_until(&mut server, &mut client, TIMEOUT, |server, client| {
+			client.receive();
+			if last_resend.elapsed() > Duration::from_millis(50) {
+				client.resend_unacknowledged();
+				last_resend = Instant::now();
+			}
+			received.extend(server.new_data_packets.drain(..).filter(|packet| packet.data_type == DataType::Data));
+			!received.is_empty() && client.unacknowledged_count() == 0
+		});
+		//Duplicates and resends of chunks, that already arrived, do not complete the message again:
+		assert_eq!(received.len(), 1);
+		assert_eq!(received[0].data, data);
+		let statistics = server.connection_statistics(&address).unwrap();
+		assert!(statistics.duplicate_messages > 0);
+		assert_eq!(statistics.fragment_groups_completed, 1);
+	}
+}
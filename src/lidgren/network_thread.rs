@@ -0,0 +1,176 @@
+use crate::prelude::*;
+
+use std::net::SocketAddr;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender, TryIter, TryRecvError};
+use std::thread::JoinHandle;
+use std::time::Instant;
+
+use crate::lidgren::connection_statistics::ConnectionStatistics;
+use crate::lidgren::lidgren_server::{DataPacket, ServerInstance};
+use crate::lidgren::message_type::MessageType;
+use crate::lidgren::transport::Waker;
+use crate::lidgren::send_priority::SendPriority;
+
+//Everything the game loop can ask the network thread to do:
+enum NetworkCommand {
+	SendVia(SocketAddr, MessageType, Vec<u8>),
+	Disconnect(SocketAddr, String),
+	AnswerDiscovery(SocketAddr, Vec<u8>),
+	AnswerConnect(SocketAddr),
+	RejectConnect(SocketAddr, String),
+	QueryStatistics(Sender<Vec<(SocketAddr, ConnectionStatistics)>>),
+	Shutdown,
+}
+
+//Runs the server (socket, acknowledges, resends, fragment reassembly) on its own thread.
+//The game loop only gets complete packets and hands over outgoing messages, a slow tick won't stall the connections.
+pub struct NetworkThread {
+	commands: Sender<NetworkCommand>,
+	packets: Receiver<DataPacket>,
+	//The network thread sleeps until traffic or a deadline, commands have to wake it up:
+	waker: Waker,
+	thread: Option<JoinHandle<()>>,
+}
+
+impl NetworkThread {
+	pub fn start(server: ServerInstance) -> NetworkThread {
+		let (command_sender, command_receiver) = channel();
+		let (packet_sender, packet_receiver) = channel();
+		let waker = server.waker();
+		let thread = std::thread::Builder::new()
+			.name(String::from("Network"))
+			.spawn(move || NetworkThread::run(server, command_receiver, packet_sender))
+			.expect("Failed to start network thread");
+		NetworkThread {
+			commands: command_sender,
+			packets: packet_receiver,
+			waker,
+			thread: Some(thread),
+		}
+	}
+	
+	fn run(mut server: ServerInstance, commands: Receiver<NetworkCommand>, packets: Sender<DataPacket>) {
+		loop {
+			//Once the game loop is gone, the channels disconnect and there is nobody to serve anymore:
+			loop {
+				match commands.try_recv() {
+					Ok(NetworkCommand::Shutdown) | Err(TryRecvError::Disconnected) => return,
+					Ok(command) => NetworkThread::execute(&mut server, command),
+					Err(TryRecvError::Empty) => break,
+				}
+			}
+			server.heartbeat();
+			for packet in server.new_data_packets.drain(..) {
+				if packets.send(packet).is_err() {
+					return;
+				}
+			}
+			server.wait_for_work();
+		}
+	}
+	
+	fn execute(server: &mut ServerInstance, command: NetworkCommand) {
+		match command {
+			NetworkCommand::SendVia(address, message_type, data) => {
+				//The client might have disconnected, while the game loop was still working on its packets:
+				if !server.is_connected(&address) {
+					log_debug!("Dropping message to ", address, ", as it is no longer connected.");
+					return;
+				}
+				server.send_via(address, message_type, data);
+			}
+			NetworkCommand::Disconnect(address, reason) => {
+				if server.is_connected(&address) {
+					server.disconnect(address, &reason);
+				}
+			}
+			NetworkCommand::AnswerDiscovery(address, payload) => server.answer_discovery(&address, &payload),
+			NetworkCommand::AnswerConnect(address) => server.answer_connect(&address),
+			NetworkCommand::RejectConnect(address, reason) => server.reject_connect(&address, &reason),
+			NetworkCommand::QueryStatistics(reply) => {
+				//The querying side might have given up waiting, nothing to do then:
+				let _ = reply.send(server.all_connection_statistics());
+			}
+			NetworkCommand::Shutdown => {} //Handled by the loop.
+		}
+	}
+	
+	fn command(&self, command: NetworkCommand) {
+		if self.commands.send(command).is_err() {
+			log_error!("Network thread is no longer running, cannot send command.");
+			return;
+		}
+		(self.waker)();
+	}
+	
+	//All packets (and connection events) the network thread received since the last call:
+	pub fn receive_packets(&self) -> TryIter<'_, DataPacket> {
+		self.packets.try_iter()
+	}
+	
+	//Blocks until a packet arrives or the deadline passed (None waits for as long as it takes):
+	pub fn wait_for_packet(&self, deadline: Option<Instant>) -> EhResult<Option<DataPacket>> {
+		let result = match deadline {
+			Some(deadline) => self.packets.recv_timeout(deadline.saturating_duration_since(Instant::now())),
+			None => self.packets.recv().map_err(|_| RecvTimeoutError::Disconnected),
+		};
+		match result {
+			Ok(packet) => Ok(Some(packet)),
+			Err(RecvTimeoutError::Timeout) => Ok(None),
+			Err(RecvTimeoutError::Disconnected) => exception!("Network thread stopped running"),
+		}
+	}
+	
+	pub fn send_to(&self, address: SocketAddr, data: Vec<u8>) {
+		self.send_with_priority(address, SendPriority::Normal, data);
+	}
+	
+	pub fn send_with_priority(&self, address: SocketAddr, priority: SendPriority, data: Vec<u8>) {
+		self.send_via(address, priority.message_type(), data);
+	}
+	
+	pub fn send_via(&self, address: SocketAddr, message_type: MessageType, data: Vec<u8>) {
+		self.command(NetworkCommand::SendVia(address, message_type, data));
+	}
+	
+	pub fn disconnect(&self, address: SocketAddr, reason: &str) {
+		self.command(NetworkCommand::Disconnect(address, reason.to_owned()));
+	}
+	
+	pub fn answer_discovery(&self, remote_address: &SocketAddr, discovery_payload: &[u8]) {
+		self.command(NetworkCommand::AnswerDiscovery(*remote_address, discovery_payload.to_vec()));
+	}
+	
+	pub fn answer_connect(&self, remote_address: &SocketAddr) {
+		self.command(NetworkCommand::AnswerConnect(*remote_address));
+	}
+	
+	pub fn reject_connect(&self, remote_address: &SocketAddr, reason: &str) {
+		self.command(NetworkCommand::RejectConnect(*remote_address, reason.to_owned()));
+	}
+	
+	//Blocks until the network thread answered, which takes at most one of its iterations:
+	pub fn all_connection_statistics(&self) -> Vec<(SocketAddr, ConnectionStatistics)> {
+		let (reply_sender, reply_receiver) = channel();
+		self.command(NetworkCommand::QueryStatistics(reply_sender));
+		reply_receiver.recv().unwrap_or_default()
+	}
+	
+	pub fn connection_statistics(&self, address: &SocketAddr) -> Option<ConnectionStatistics> {
+		self.all_connection_statistics().into_iter()
+			.find(|(connection_address, _)| connection_address == address)
+			.map(|(_, statistics)| statistics)
+	}
+}
+
+impl Drop for NetworkThread {
+	fn drop(&mut self) {
+		let _ = self.commands.send(NetworkCommand::Shutdown);
+		(self.waker)();
+		if let Some(thread) = self.thread.take() {
+			if thread.join().is_err() {
+				log_error!("Network thread panicked.");
+			}
+		}
+	}
+}
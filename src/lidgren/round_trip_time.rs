@@ -0,0 +1,52 @@
+use std::time::Duration;
+
+//Used until the first pong arrived, same value that was used before measuring:
+const DEFAULT_RESEND_TIMEOUT: f32 = 0.4;
+//Lidgren adds this to the round trip time, to give the remote some time to process the message:
+const MIN_RESEND_TIMEOUT: f32 = 0.025;
+const MAX_RESEND_TIMEOUT: f32 = 5.0;
+
+//Smoothed round trip time and its variance in seconds, calculated like TCP does it (RFC 6298).
+#[derive(Default)]
+pub struct RoundTripTime {
+	smoothed: Option<f32>,
+	variance: f32,
+}
+
+impl RoundTripTime {
+	pub fn add_sample(&mut self, sample: Duration) {
+		let sample = sample.as_secs_f32();
+		match self.smoothed {
+			None => {
+				self.smoothed = Some(sample);
+				self.variance = sample / 2.0;
+			}
+			Some(smoothed) => {
+				self.variance = 0.75 * self.variance + 0.25 * (smoothed - sample).abs();
+				self.smoothed = Some(0.875 * smoothed + 0.125 * sample);
+			}
+		}
+	}
+	
+	pub fn smoothed(&self) -> Option<Duration> {
+		self.smoothed.map(Duration::from_secs_f32)
+	}
+	
+	pub fn variance(&self) -> Duration {
+		Duration::from_secs_f32(self.variance)
+	}
+	
+	pub fn resend_timeout(&self) -> Duration {
+		let timeout = match self.smoothed {
+			None => DEFAULT_RESEND_TIMEOUT,
+			Some(smoothed) => smoothed + (4.0 * self.variance).max(MIN_RESEND_TIMEOUT),
+		};
+		Duration::from_secs_f32(timeout.clamp(MIN_RESEND_TIMEOUT, MAX_RESEND_TIMEOUT))
+	}
+	
+	//Every time a message has to be resent again, the timeout doubles, to not flood a bad connection:
+	pub fn resend_timeout_with_backoff(&self, sent_count: u32) -> Duration {
+		let factor = 1u32 << sent_count.saturating_sub(1).min(8);
+		self.resend_timeout().saturating_mul(factor).min(Duration::from_secs_f32(MAX_RESEND_TIMEOUT))
+	}
+}
@@ -0,0 +1,37 @@
+use crate::lidgren::message_type::MessageType;
+
+//Reliable ordered channel for small messages that should not wait for bulk transfers (like chat):
+pub const HIGH_PRIORITY_CHANNEL: u8 = 1;
+
+//When the bandwidth budget does not allow sending everything, the higher priority is sent first.
+//A single channel is always delivered in order, hence each priority has its own channel - else a small
+// message would still wait for all fragments queued before it.
+//Big transfers (like the world) stay on the normal channel, as the world updates that follow them must not
+// overtake them. The budget lets the high priority channel interleave with them.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SendPriority {
+	High,
+	Normal,
+}
+
+impl SendPriority {
+	//In the order in which the senders get to fill the datagrams:
+	pub const ALL: [SendPriority; 2] = [SendPriority::High, SendPriority::Normal];
+	
+	//Channel to send reliable messages of this priority on:
+	pub const fn message_type(&self) -> MessageType {
+		match self {
+			SendPriority::High => MessageType::UserReliableOrdered(HIGH_PRIORITY_CHANNEL),
+			SendPriority::Normal => MessageType::UserReliableOrdered(0),
+		}
+	}
+	
+	pub const fn of(message_type: &MessageType) -> SendPriority {
+		match message_type {
+			//Unreliable messages are outdated soon (like positions), so they are sent first:
+			MessageType::UserUnreliable | MessageType::UserSequenced(_) => SendPriority::High,
+			MessageType::UserReliableOrdered(HIGH_PRIORITY_CHANNEL) => SendPriority::High,
+			_ => SendPriority::Normal,
+		}
+	}
+}
@@ -0,0 +1,229 @@
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use crate::lidgren::data_structures::{MESSAGE_HEADER_LENGTH, MessageHeader, SEQUENCE_NUMBERS};
+use crate::lidgren::data_types::DataType;
+use crate::lidgren::lidgren_server::{DataPacket, ServerInstance};
+use crate::lidgren::message_type::MessageType;
+use crate::lidgren::transport::{LoopbackTransport, Transport};
+use crate::lidgren::util::formatter as lg_formatter;
+use crate::util::custom_iterator::CustomIterator;
+
+pub const APPLICATION_NAME: &str = "Logic World";
+
+//The bare minimum of a Lidgren client, to drive the server from tests over the loopback transport.
+//Answers pings, acknowledges what the server sends and resends its own reliable messages until they got acknowledged.
+//Only the default reliable ordered channel is used for sending.
+pub struct TestClient {
+	transport: LoopbackTransport,
+	server_address: SocketAddr,
+	input_buffer: Vec<u8>,
+	//Turned off, to see the server resend its messages:
+	pub acknowledge: bool,
+	connect_response: Option<Vec<u8>>,
+	next_sequence_number: u16,
+	//Complete messages sent to the server, which it did not acknowledge yet:
+	unacknowledged: HashMap<u16, Vec<u8>>,
+	//Reliable messages of the server, that got handled already. Resends of them are dropped:
+	received_reliable: HashSet<(u8, u16)>,
+	//Chunks of fragmented messages by group, each chunk at its index:
+	fragment_groups: HashMap<u32, Vec<Option<Vec<u8>>>>,
+}
+
+impl TestClient {
+	pub fn new(transport: LoopbackTransport, server_address: SocketAddr) -> TestClient {
+		TestClient {
+			transport,
+			server_address,
+			input_buffer: vec![0; 0xFFFF],
+			acknowledge: true,
+			connect_response: None,
+			next_sequence_number: 0,
+			unacknowledged: HashMap::new(),
+			received_reliable: HashSet::new(),
+			fragment_groups: HashMap::new(),
+		}
+	}
+	
+	pub fn address(&self) -> SocketAddr {
+		self.transport.address()
+	}
+	
+	//Payload of the connect response, once one arrived:
+	pub fn connect_response(&self) -> Option<&Vec<u8>> {
+		self.connect_response.as_ref()
+	}
+	
+	pub fn unacknowledged_count(&self) -> usize {
+		self.unacknowledged.len()
+	}
+	
+	fn send_message(&self, message_type: MessageType, fragment: bool, sequence_number: u16, payload: &[u8]) -> Vec<u8> {
+		let mut message = Vec::with_capacity(MESSAGE_HEADER_LENGTH + payload.len());
+		MessageHeader::write(&mut message, &message_type, fragment, sequence_number, payload.len());
+		message.extend_from_slice(payload);
+		self.transport.send(&message, &self.server_address);
+		message
+	}
+	
+	pub fn send_connect(&self, hail: &[u8]) {
+		let mut payload = Vec::new();
+		lg_formatter::write_string(&mut payload, APPLICATION_NAME);
+		lg_formatter::write_int_64(&mut payload, 0x1234_5678);
+		lg_formatter::write_float(&mut payload, 0.0);
+		payload.extend_from_slice(hail);
+		self.send_message(MessageType::Connect, false, 0, &payload);
+	}
+	
+	pub fn send_connection_established(&self) {
+		let mut payload = Vec::new();
+		lg_formatter::write_float(&mut payload, 0.0);
+		self.send_message(MessageType::ConnectionEstablished, false, 0, &payload);
+	}
+	
+	pub fn send_reliable(&mut self, payload: &[u8]) {
+		let sequence_number = self.next_sequence_number();
+		let message = self.send_message(MessageType::UserReliableOrdered(0), false, sequence_number, payload);
+		self.unacknowledged.insert(sequence_number, message);
+	}
+	
+	//Splits the data into chunks like Lidgren does. The last chunk is sent first, so that the server has to
+	// hold back the others until the channel is in order again:
+	pub fn send_fragmented(&mut self, group: u32, data: &[u8], chunk_size: usize) {
+		let chunks: Vec<&[u8]> = data.chunks(chunk_size).collect();
+		let mut messages = Vec::with_capacity(chunks.len());
+		for (index, chunk) in chunks.iter().enumerate() {
+			let mut payload = Vec::new();
+			lg_formatter::write_vint_32(&mut payload, group);
+			lg_formatter::write_vint_32(&mut payload, data.len() as u32 * 8);
+			lg_formatter::write_vint_32(&mut payload, chunk_size as u32);
+			lg_formatter::write_vint_32(&mut payload, index as u32);
+			payload.extend_from_slice(chunk);
+			messages.push((self.next_sequence_number(), payload));
+		}
+		for (sequence_number, payload) in messages.into_iter().rev() {
+			let message = self.send_message(MessageType::UserReliableOrdered(0), true, sequence_number, &payload);
+			self.unacknowledged.insert(sequence_number, message);
+		}
+	}
+	
+	fn next_sequence_number(&mut self) -> u16 {
+		let sequence_number = self.next_sequence_number;
+		self.next_sequence_number = (self.next_sequence_number + 1) % SEQUENCE_NUMBERS;
+		sequence_number
+	}
+	
+	//Sends every reliable message again, that the server did not acknowledge yet:
+	pub fn resend_unacknowledged(&self) {
+		for message in self.unacknowledged.values() {
+			self.transport.send(message, &self.server_address);
+		}
+	}
+	
+	//Handles everything the server sent, returns the user messages (with their header) in the order they arrived.
+	//Resends of reliable messages are included, they are only dropped by 'receive_data':
+	pub fn receive(&mut self) -> Vec<(MessageHeader, Vec<u8>)> {
+		let mut user_messages = Vec::new();
+		let mut acknowledges = Vec::new();
+		while let Some((amount, _)) = self.transport.receive(&mut self.input_buffer) {
+			let mut iterator = CustomIterator::borrow(&self.input_buffer[..amount]);
+			while iterator.remaining() >= MESSAGE_HEADER_LENGTH {
+				let header = MessageHeader::from_stream(&mut iterator).expect("Server sent invalid message header");
+				let payload = iterator.read_bytes(header.bytes as usize).expect("Server sent message bigger than the datagram");
+				match header.message_type {
+					MessageType::Ping => {
+						let mut pong = vec![payload[0]];
+						lg_formatter::write_float(&mut pong, 0.0);
+						self.send_message(MessageType::Pong, false, 0, &pong);
+					}
+					MessageType::Acknowledge => {
+						for acknowledge in payload.chunks(3) {
+							self.unacknowledged.remove(&(acknowledge[1] as u16 | ((acknowledge[2] as u16) << 8)));
+						}
+					}
+					MessageType::ConnectResponse => self.connect_response = Some(payload),
+					message_type if !MessageType::is_system(&message_type) => {
+						if self.acknowledge && MessageType::is_reliable(&message_type) {
+							acknowledges.push(message_type.to_index());
+							acknowledges.push(header.sequence_number as u8);
+							acknowledges.push((header.sequence_number >> 8) as u8);
+						}
+						user_messages.push((header, payload));
+					}
+					_ => {} //MTU probes stay unanswered, the default MTU is fine.
+				}
+			}
+		}
+		if !acknowledges.is_empty() {
+			self.send_message(MessageType::Acknowledge, false, 0, &acknowledges);
+		}
+		user_messages
+	}
+	
+	//Like 'receive', but drops resends and returns reassembled fragmented messages:
+	pub fn receive_data(&mut self) -> Vec<Vec<u8>> {
+		let mut data = Vec::new();
+		for (header, payload) in self.receive() {
+			if !self.received_reliable.insert((header.message_type.to_index(), header.sequence_number)) {
+				continue;
+			}
+			if !header.fragment {
+				data.push(payload);
+				continue;
+			}
+			let mut iterator = CustomIterator::borrow(&payload);
+			let group = lg_formatter::read_vint_32(&mut iterator).unwrap();
+			let total_bits = lg_formatter::read_vint_32(&mut iterator).unwrap();
+			let chunk_size = lg_formatter::read_vint_32(&mut iterator).unwrap();
+			let index = lg_formatter::read_vint_32(&mut iterator).unwrap();
+			let chunk_count = (total_bits / 8).div_ceil(chunk_size) as usize;
+			let chunks = self.fragment_groups.entry(group).or_insert_with(|| vec![None; chunk_count]);
+			chunks[index as usize] = Some(iterator.consume());
+			if chunks.iter().all(|chunk| chunk.is_some()) {
+				data.push(self.fragment_groups.remove(&group).unwrap().into_iter().flatten().flatten().collect());
+			}
+		}
+		data
+	}
+}
+
+//Runs the server, until the condition is met. The condition gets to run the client:
+pub fn run_until(server: &mut ServerInstance, client: &mut TestClient, timeout: Duration, mut condition: impl FnMut(&mut ServerInstance, &mut TestClient) -> bool) {
+	let deadline = Instant::now() + timeout;
+	while !condition(server, client) {
+		assert!(Instant::now() < deadline, "Condition was not met in time");
+		server.heartbeat();
+		std::thread::sleep(Duration::from_millis(1));
+	}
+}
+
+//Connects the client to the server, resending the connect and confirmation like a real client would.
+//Returns the events the server reported meanwhile, the connect (with its hail data) included:
+pub fn connect(server: &mut ServerInstance, client: &mut TestClient, timeout: Duration) -> Vec<DataPacket> {
+	let deadline = Instant::now() + timeout;
+	let mut events = Vec::new();
+	let mut last_attempt = None;
+	while !server.is_connected(&client.address()) {
+		assert!(Instant::now() < deadline, "Client did not connect in time");
+		//Not too often, the flood protection only allows a few connects at once:
+		if last_attempt.is_none_or(|time: Instant| time.elapsed() > Duration::from_millis(250)) {
+			match client.connect_response() {
+				None => client.send_connect(b"hail"),
+				Some(_) => client.send_connection_established(),
+			}
+			last_attempt = Some(Instant::now());
+		}
+		server.heartbeat();
+		let packets: Vec<DataPacket> = server.new_data_packets.drain(..).collect();
+		for packet in packets {
+			if packet.data_type == DataType::Connect {
+				server.answer_connect(&packet.remote_address);
+			}
+			events.push(packet);
+		}
+		client.receive();
+		std::thread::sleep(Duration::from_millis(1));
+	}
+	events
+}
@@ -0,0 +1,371 @@
+use crate::prelude::*;
+
+use std::collections::{HashMap, VecDeque};
+use std::io::ErrorKind::WouldBlock;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+//Whatever carries the datagrams of the server. Like UDP there are no delivery guarantees,
+// failing to send is logged and otherwise treated like a lost datagram.
+//Has to be 'Send', as the server runs on the network thread.
+pub trait Transport: Send {
+	fn send(&self, data: &[u8], address: &SocketAddr);
+	
+	//Copies the next datagram into the buffer, returns its size and sender. None if there is none right now.
+	fn receive(&mut self, input_buffer: &mut [u8]) -> Option<(usize, SocketAddr)>;
+	
+	//Blocks until a datagram can be received, the timeout passed (None waits forever) or the waker got called.
+	//May return early without reason, callers just check everything again.
+	fn wait_readable(&mut self, timeout: Option<Duration>);
+	
+	//Interrupts 'wait_readable' from another thread, when there is something else to do:
+	fn waker(&self) -> Waker;
+}
+
+pub type Waker = Arc<dyn Fn() + Send + Sync>;
+
+//Lets transports wrap each other, without knowing which ones got chosen at startup:
+impl<T: Transport + ?Sized> Transport for Box<T> {
+	fn send(&self, data: &[u8], address: &SocketAddr) {
+		(**self).send(data, address);
+	}
+	
+	fn receive(&mut self, input_buffer: &mut [u8]) -> Option<(usize, SocketAddr)> {
+		(**self).receive(input_buffer)
+	}
+	
+	fn wait_readable(&mut self, timeout: Option<Duration>) {
+		(**self).wait_readable(timeout);
+	}
+	
+	fn waker(&self) -> Waker {
+		(**self).waker()
+	}
+}
+
+//Lets one thread sleep, until another one signals that there is something to do.
+//A signal that arrives before the wait started is not lost, the wait returns right away.
+#[derive(Default)]
+pub struct WakeupSignal {
+	signaled: Mutex<bool>,
+	condition: Condvar,
+}
+
+impl WakeupSignal {
+	pub fn wake(&self) {
+		*self.signaled.lock().unwrap() = true;
+		self.condition.notify_all();
+	}
+	
+	pub fn wait(&self, timeout: Option<Duration>) {
+		let signaled = self.signaled.lock().unwrap();
+		let mut signaled = match timeout {
+			Some(timeout) => self.condition.wait_timeout_while(signaled, timeout, |signaled| !*signaled).unwrap().0,
+			None => self.condition.wait_while(signaled, |signaled| !*signaled).unwrap(),
+		};
+		*signaled = false;
+	}
+}
+
+// ### UDP: ###
+
+//Without a waker, waiting may not take longer than this, else the game loop would wait for its messages to be sent:
+const UDP_WAIT_WITHOUT_WAKER: Duration = Duration::from_millis(5);
+
+pub struct UdpTransport {
+	socket: UdpSocket,
+	//A second socket, which wakes up the waiting server socket by sending it an empty datagram:
+	waker_socket: Option<Arc<UdpSocket>>,
+	waker_address: Option<SocketAddr>,
+}
+
+impl UdpTransport {
+	pub fn bind(target: String) -> EhResult<UdpTransport> {
+		let socket = UdpSocket::bind(target).map_ex(ex!("While binding server socket"))?;
+		socket.set_nonblocking(true).map_ex(ex!("While setting socket to non-blocking mode"))?;
+		let (waker_socket, waker_address) = match UdpTransport::create_waker_socket(&socket) {
+			Ok(waker_socket) => {
+				let waker_address = waker_socket.local_addr().ok();
+				(Some(Arc::new(waker_socket)), waker_address)
+			}
+			Err(error) => {
+				log_warn!("Could not create socket to wake up the network thread, it will poll instead: ", format!("{:?}", error));
+				(None, None)
+			}
+		};
+		Ok(UdpTransport {
+			socket,
+			waker_socket,
+			waker_address,
+		})
+	}
+	
+	fn create_waker_socket(socket: &UdpSocket) -> std::io::Result<UdpSocket> {
+		let local_address = socket.local_addr()?;
+		//A server listening on all interfaces is reachable via loopback:
+		let target_ip = match local_address.ip() {
+			IpAddr::V4(ip) if ip.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+			IpAddr::V6(ip) if ip.is_unspecified() => IpAddr::V6(Ipv6Addr::LOCALHOST),
+			ip => ip,
+		};
+		let waker_socket = UdpSocket::bind(SocketAddr::new(target_ip, 0))?;
+		waker_socket.connect(SocketAddr::new(target_ip, local_address.port()))?;
+		Ok(waker_socket)
+	}
+}
+
+impl Transport for UdpTransport {
+	fn send(&self, data: &[u8], address: &SocketAddr) {
+		match self.socket.send_to(data, address) {
+			Ok(number_bytes) => {
+				if number_bytes != data.len() {
+					log_error!("ERROR: Failed to send right amount of bytes via socket ", number_bytes, " / ", data.len());
+				}
+			}
+			Err(err) => {
+				//Lidgren resends reliable messages, so losing this datagram is not fatal:
+				log_error!("Error while sending via socket: ", format!("{:?}", err));
+			}
+		}
+	}
+	
+	fn receive(&mut self, input_buffer: &mut [u8]) -> Option<(usize, SocketAddr)> {
+		loop {
+			match self.socket.recv_from(input_buffer) {
+				Err(err) if err.kind() == WouldBlock => {
+					return None; //No packet in buffer right now, skip reading.
+				}
+				Err(err) => {
+					log_error!("Error while reading from socket: ", format!("{:?}", err));
+					return None; //We got an error, so stop reading
+				}
+				Ok((_, address)) if Some(address) == self.waker_address => {
+					continue; //Only there to end the wait, not a real packet.
+				}
+				Ok(value) => {
+					return Some(value); //All good, forward packet!
+				}
+			}
+		}
+	}
+	
+	fn wait_readable(&mut self, timeout: Option<Duration>) {
+		let timeout = match (timeout, &self.waker_socket) {
+			(Some(timeout), None) => Some(timeout.min(UDP_WAIT_WITHOUT_WAKER)),
+			(None, None) => Some(UDP_WAIT_WITHOUT_WAKER),
+			(timeout, _) => timeout,
+		};
+		if timeout.is_some_and(|timeout| timeout.is_zero()) {
+			return; //A zero timeout would mean blocking forever.
+		}
+		//Peeking in blocking mode waits for the next datagram, without taking it. Receiving stays non-blocking:
+		if let Err(error) = self.socket.set_nonblocking(false).and_then(|_| self.socket.set_read_timeout(timeout)) {
+			log_error!("Could not switch socket to blocking mode: ", format!("{:?}", error));
+			std::thread::sleep(UDP_WAIT_WITHOUT_WAKER);
+			return;
+		}
+		//Whatever the result is (datagram, timeout or error), the receiving side deals with it:
+		let _ = self.socket.peek_from(&mut [0; 1]);
+		if let Err(error) = self.socket.set_nonblocking(true) {
+			log_error!("Could not switch socket back to non-blocking mode: ", format!("{:?}", error));
+		}
+	}
+	
+	fn waker(&self) -> Waker {
+		match &self.waker_socket {
+			Some(waker_socket) => {
+				let waker_socket = waker_socket.clone();
+				Arc::new(move || {
+					//If this gets lost, the wait still ends at the next deadline:
+					let _ = waker_socket.send(&[]);
+				})
+			}
+			None => Arc::new(|| {}),
+		}
+	}
+}
+
+// ### In-memory loopback: ###
+
+//Connects loopback transports with each other, datagrams are delivered instantly and in order.
+//Nothing touches the operating system, which allows driving the whole Lidgren stack deterministically.
+#[derive(Default)]
+struct LoopbackEndpoint {
+	//Sender and content of each queued datagram:
+	inbox: VecDeque<(SocketAddr, Vec<u8>)>,
+	signal: Arc<WakeupSignal>,
+}
+
+#[derive(Clone, Default)]
+pub struct LoopbackNetwork {
+	endpoints: Arc<Mutex<HashMap<SocketAddr, LoopbackEndpoint>>>,
+}
+
+impl LoopbackNetwork {
+	pub fn new() -> LoopbackNetwork {
+		Self::default()
+	}
+	
+	//Creates the transport for an address, datagrams sent to that address are queued for it:
+	pub fn bind(&self, address: SocketAddr) -> LoopbackTransport {
+		let signal = self.endpoints.lock().unwrap().entry(address).or_default().signal.clone();
+		LoopbackTransport {
+			network: self.clone(),
+			address,
+			signal,
+		}
+	}
+	
+	//Amount of datagrams waiting to be received by the address:
+	pub fn pending(&self, address: &SocketAddr) -> usize {
+		self.endpoints.lock().unwrap().get(address).map_or(0, |endpoint| endpoint.inbox.len())
+	}
+}
+
+pub struct LoopbackTransport {
+	network: LoopbackNetwork,
+	address: SocketAddr,
+	signal: Arc<WakeupSignal>,
+}
+
+impl LoopbackTransport {
+	pub fn address(&self) -> SocketAddr {
+		self.address
+	}
+}
+
+impl Transport for LoopbackTransport {
+	fn send(&self, data: &[u8], address: &SocketAddr) {
+		let mut endpoints = self.network.endpoints.lock().unwrap();
+		match endpoints.get_mut(address) {
+			Some(endpoint) => {
+				endpoint.inbox.push_back((self.address, data.to_vec()));
+				endpoint.signal.wake();
+			}
+			None => log_debug!("Loopback datagram to unbound address ", address, " got lost."), //Just like UDP.
+		}
+	}
+	
+	fn receive(&mut self, input_buffer: &mut [u8]) -> Option<(usize, SocketAddr)> {
+		let (sender, data) = self.network.endpoints.lock().unwrap().get_mut(&self.address)?.inbox.pop_front()?;
+		//Like UDP, whatever does not fit into the buffer is cut off:
+		let amount = data.len().min(input_buffer.len());
+		input_buffer[..amount].copy_from_slice(&data[..amount]);
+		Some((amount, sender))
+	}
+	
+	fn wait_readable(&mut self, timeout: Option<Duration>) {
+		if self.network.pending(&self.address) > 0 {
+			return;
+		}
+		self.signal.wait(timeout);
+	}
+	
+	fn waker(&self) -> Waker {
+		let signal = self.signal.clone();
+		Arc::new(move || signal.wake())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	use std::time::Instant;
+	
+	use crate::lidgren::data_types::DataType;
+	use crate::lidgren::lidgren_server::ServerInstance;
+	use crate::lidgren::test_client::{self, APPLICATION_NAME, TestClient, run_until};
+	use crate::lidgren::util::formatter as lg_formatter;
+	use crate::util::custom_iterator::CustomIterator;
+	
+	const TIMEOUT: Duration = Duration::from_secs(5);
+	
+	fn start() -> (ServerInstance, TestClient) {
+		let network = LoopbackNetwork::new();
+		let server_address: SocketAddr = "10.0.0.1:43531".parse().unwrap();
+		let server = ServerInstance::with_transport(String::from(APPLICATION_NAME), 42, Box::new(network.bind(server_address)));
+		let client = TestClient::new(network.bind("10.0.0.2:50000".parse().unwrap()), server_address);
+		(server, client)
+	}
+	
+	#[test]
+	fn handshake() {
+		let (mut server, mut client) = start();
+		let events = test_client::connect(&mut server, &mut client, TIMEOUT);
+		
+		let connect = events.iter().find(|event| event.data_type == DataType::Connect).expect("Server did not report the connect");
+		assert_eq!(connect.remote_address, client.address());
+		assert_eq!(connect.data, b"hail");
+		assert!(events.iter().any(|event| event.data_type == DataType::Connected && event.remote_address == client.address()));
+		
+		let response = client.connect_response().unwrap();
+		let mut iterator = CustomIterator::borrow(response);
+		assert_eq!(lg_formatter::read_string(&mut iterator).unwrap(), APPLICATION_NAME);
+		assert_eq!(lg_formatter::read_int_64(&mut iterator).unwrap(), 42);
+	}
+	
+	#[test]
+	fn fragments_are_reassembled() {
+		let (mut server, mut client) = start();
+		test_client::connect(&mut server, &mut client, TIMEOUT);
+		
+		//Client to server, the chunks arrive in reverse order:
+		let data: Vec<u8> = (0..5000).map(|index| (index % 251) as u8).collect();
+		client.send_fragmented(1, &data, 1000);
+		let mut received = Vec::new();
+		run_until(&mut server, &mut client, TIMEOUT, |server, client| {
+			client.receive();
+			received.extend(server.new_data_packets.drain(..).filter(|packet| packet.data_type == DataType::Data));
+			!received.is_empty() && client.unacknowledged_count() == 0
+		});
+		assert_eq!(received.len(), 1);
+		assert_eq!(received[0].data, data);
+		
+		//Server to client, bigger than the MTU:
+		let data: Vec<u8> = (0..20000).map(|index| (index % 253) as u8).collect();
+		server.send_to(client.address(), data.clone());
+		let mut received = Vec::new();
+		run_until(&mut server, &mut client, TIMEOUT, |_, client| {
+			received.extend(client.receive_data());
+			!received.is_empty()
+		});
+		assert_eq!(received, vec![data]);
+	}
+	
+	#[test]
+	fn unacknowledged_messages_are_resent() {
+		let (mut server, mut client) = start();
+		test_client::connect(&mut server, &mut client, TIMEOUT);
+		let address = client.address();
+		//With a round trip time, the resend timeout is short:
+		run_until(&mut server, &mut client, TIMEOUT, |server, client| {
+			client.receive();
+			server.connection_statistics(&address).unwrap().round_trip_time.is_some()
+		});
+		
+		client.acknowledge = false;
+		server.send_to(address, b"Hello".to_vec());
+		let mut copies = Vec::new();
+		run_until(&mut server, &mut client, TIMEOUT, |_, client| {
+			copies.extend(client.receive().into_iter().filter(|(_, payload)| payload == b"Hello"));
+			copies.len() >= 2
+		});
+		assert_eq!(copies[0].0.sequence_number, copies[1].0.sequence_number);
+		assert!(server.connection_statistics(&address).unwrap().messages_resent >= 1);
+		
+		//Once acknowledged, the message is not sent anymore:
+		client.acknowledge = true;
+		run_until(&mut server, &mut client, TIMEOUT, |server, client| {
+			client.receive();
+			server.connection_statistics(&address).unwrap().send_window_occupancy == 0
+		});
+		let quiet_until = Instant::now() + Duration::from_millis(300);
+		while Instant::now() < quiet_until {
+			server.heartbeat();
+			assert!(client.receive().is_empty(), "Acknowledged message got resent");
+			std::thread::sleep(Duration::from_millis(1));
+		}
+	}
+}
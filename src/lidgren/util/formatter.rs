@@ -40,9 +40,9 @@ pub fn read_int_32(iterator: &mut CustomIterator) -> EhResult<u32> {
 		return exception!("Ran out of bytes, while reading int_32: ", iterator.remaining(), "/", 4);
 	}
 	Ok((iterator.next_unchecked() as u32) |
-		(iterator.next_unchecked() as u32) >> 8 |
-		(iterator.next_unchecked() as u32) >> 16 |
-		(iterator.next_unchecked() as u32) >> 24)
+		(iterator.next_unchecked() as u32) << 8 |
+		(iterator.next_unchecked() as u32) << 16 |
+		(iterator.next_unchecked() as u32) << 24)
 }
 
 pub fn write_int_32(buffer: &mut Vec<u8>, value: u32) {
@@ -1,8 +1,7 @@
 use rust_potato_server::prelude::*;
 
 use std::net::SocketAddr;
-use std::ops::Sub;
-use std::thread::sleep;
+use std::path::Path;
 use std::time::{Duration, Instant};
 use rand::Rng;
 
@@ -19,15 +18,29 @@ use network::packets::compression::try_decompress;
 use network::message_pack::reader as mp_reader;
 use network::message_pack::pretty_printer::pretty_print_data;
 use lidgren::lidgren_server::ServerInstance;
+use lidgren::network_thread::NetworkThread;
+use lidgren::transport::{Transport, UdpTransport};
+use lidgren::capture::{RecordingTransport, ReplaySession};
+use lidgren::network_simulator::{Impairment, NetworkSimulator};
 use rust_potato_server::files::extra_data::manager::ExtraDataManager;
 use rust_potato_server::files::world_data::world_file_parser;
-use rust_potato_server::files::world_data::world_structs::World;
 use rust_potato_server::files::world_files::WorldFolderAccess;
+use rust_potato_server::files::access_lists::AccessLists;
 use rust_potato_server::lidgren::data_types::DataType;
 use rust_potato_server::network::packets::c2s::connection_established::ConnectionEstablished;
+use rust_potato_server::network::packets::c2s::client_loaded_world::ClientLoadedWorld;
+use rust_potato_server::network::packets::c2s::chat_message_sent::ChatMessageSent;
 use rust_potato_server::network::packets::c2s::player_position::PlayerPosition;
+use rust_potato_server::network::packets::c2s::run_command::RunCommand;
+use rust_potato_server::network::packets::c2s::building_request::BuildingRequest;
 use rust_potato_server::network::packets::packet_ids::PacketIDs;
 use rust_potato_server::network::packets::s2c::world_initialization_packet::WorldInitializationPacket;
+use rust_potato_server::server::player_sessions::{PlayerSessions, SessionState};
+use rust_potato_server::server::chat::Chat;
+use rust_potato_server::server::building;
+use rust_potato_server::server::build_validation::BuildLimits;
+use rust_potato_server::server::commands::CommandRegistry;
+use rust_potato_server::server::game_state::GameState;
 use util::custom_iterator::CustomIterator;
 
 fn main() {
@@ -35,49 +48,115 @@ fn main() {
 	
 	log_info!("Starting file reading!");
 	let folders = unwrap_or_print_return!(WorldFolderAccess::initialize());
-	let mut extra_data = unwrap_or_print_return!(ExtraDataManager::initialize(&folders));
-	let mut world = unwrap_or_print_return!(world_file_parser::load_world(&folders));
+	let extra_data = unwrap_or_print_return!(ExtraDataManager::initialize(&folders));
+	let world = unwrap_or_print_return!(world_file_parser::load_world(&folders));
+	let access_lists = unwrap_or_print_return!(AccessLists::load(&folders.data_folder));
+	let mut state = GameState {
+		folders,
+		world,
+		extra_data,
+		access_lists,
+		sessions: PlayerSessions::default(),
+		chat: Chat::default(),
+		build_limits: BuildLimits::default(),
+	};
+	let commands = CommandRegistry::with_defaults();
+	
+	//Optional traffic capture: '--record <file>' writes all datagrams to a file, '--replay <file>' runs a recorded session again.
+	//'--simulate-network <settings>' impairs the traffic in both directions (see 'Impairment::parse'), '--simulate-seed <number>' repeats a run.
+	let arguments: Vec<String> = std::env::args().collect();
+	let argument_value = |name: &str| arguments.iter().position(|argument| argument == name).and_then(|index| arguments.get(index + 1));
 	
 	log_info!("Starting network socket!");
 	let mut rand = rand::thread_rng();
 	let random_unique_id = rand.gen();
-	let mut server = unwrap_or_print_return!(ServerInstance::start(
+	let mut replay_session = None;
+	let transport: Box<dyn Transport> = if let Some(path) = argument_value("--replay") {
+		log_warn!("Replaying capture ", path, ", changes made by the recorded clients will be applied to the world!");
+		let session = unwrap_or_print_return!(ReplaySession::load(Path::new(path)));
+		let transport = session.transport();
+		replay_session = Some(session);
+		Box::new(transport)
+	} else {
+		let udp = unwrap_or_print_return!(UdpTransport::bind(String::from("[::]:43531")).wrap(ex!("While starting network server")));
+		//Recorded is what the server sees, so the simulator goes below the recording:
+		let udp: Box<dyn Transport> = match argument_value("--simulate-network") {
+			Some(settings) => {
+				let impairment = unwrap_or_print_return!(Impairment::parse(settings).wrap(ex!("While reading --simulate-network")));
+				let seed = match argument_value("--simulate-seed") {
+					Some(seed) => unwrap_or_print_return!(seed.parse::<u64>().map_ex(ex!("Expected a number for --simulate-seed, got '", seed, "'"))),
+					None => rand.gen(),
+				};
+				log_warn!("Simulating a bad network with '", settings, "' and seed ", seed);
+				Box::new(NetworkSimulator::new(udp, seed, impairment.clone(), impairment))
+			}
+			None => Box::new(udp),
+		};
+		if let Some(path) = argument_value("--record") {
+			log_info!("Recording network traffic to ", path);
+			Box::new(unwrap_or_print_return!(RecordingTransport::create(udp, Path::new(path))))
+		} else {
+			Box::new(udp)
+		}
+	};
+	let server = ServerInstance::with_transport(
 		String::from("Logic World"),
 		random_unique_id,
-		String::from("[::]:43531"),
-	).wrap(ex!("While starting network server")));
+		transport,
+	);
+	//Connections are served on their own thread, the game loop only gets the packets:
+	let network = NetworkThread::start(server);
 	
-	let mut packets_to_process = Vec::new();
-	let min_tick_duration = Duration::from_millis(16);
+	//Time the server gets to answer the last replayed datagram:
+	let replay_grace_duration = Duration::from_secs(3);
+	let replay_check_interval = Duration::from_millis(100);
+	let mut replay_finished_at: Option<Instant> = None;
 	loop {
-		let tick_start = Instant::now();
-		server.heartbeat();
-		if !server.new_data_packets.is_empty() {
-			//Swap the packets to process list, so that they can be processed, without blocking the server.
-			packets_to_process = std::mem::replace(&mut server.new_data_packets, packets_to_process);
-			for user_packet in packets_to_process.drain(..) {
-				log_debug!(">>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>");
-				match user_packet.data_type {
-					DataType::Discovery => {
-						log_debug!("=> Discovery!");
-						handle_discovery(&server, user_packet.remote_address, user_packet.data);
-					}
-					DataType::Connect => {
-						log_debug!("=> Connect!");
-						handle_connect(&server, user_packet.remote_address, user_packet.data);
-					}
-					DataType::Data => {
-						log_debug!("=> Data!");
-						handle_user_packet(&mut server, user_packet.remote_address, user_packet.data, &mut world, &mut extra_data);
+		//Sleep until a packet arrives or the next deadline. There is no simulation tick yet, so the only deadline
+		// is checking whether a replay is done. When idle, this waits without using any CPU.
+		let deadline = replay_session.as_ref().map(|_| Instant::now() + replay_check_interval);
+		let first_packet = unwrap_or_print_return!(network.wait_for_packet(deadline));
+		if let Some(session) = &replay_session {
+			if session.is_finished() {
+				let finished_at = *replay_finished_at.get_or_insert_with(Instant::now);
+				if finished_at.elapsed().ge(&replay_grace_duration) {
+					//Lets scripts notice, that the server does not behave like when the session got recorded:
+					if !session.report() {
+						std::process::exit(1);
 					}
+					return;
 				}
 			}
 		}
-		//Don't start another tick, before 16 ms are over.
-		//Subject to change in future, but for now don't let this run amok, as there is not much to do.
-		let elapsed = tick_start.elapsed();
-		if elapsed.le(&min_tick_duration) {
-			sleep(min_tick_duration.sub(elapsed));
+		for user_packet in first_packet.into_iter().chain(network.receive_packets()) {
+			log_debug!(">>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>");
+			match user_packet.data_type {
+				DataType::Discovery => {
+					log_debug!("=> Discovery!");
+					handle_discovery(&network, user_packet.remote_address, user_packet.data);
+				}
+				DataType::Connect => {
+					log_debug!("=> Connect!");
+					handle_connect(&network, user_packet.remote_address, user_packet.data, &mut state);
+				}
+				DataType::Data => {
+					log_debug!("=> Data!");
+					handle_user_packet(&network, user_packet.remote_address, user_packet.data, &mut state, &commands);
+				}
+				DataType::Connected => {
+					log_info!("=> Connected ", user_packet.remote_address.ip(), ":", user_packet.remote_address.port());
+				}
+				DataType::Disconnected | DataType::TimedOut | DataType::Kicked => {
+					log_info!("=> Lost connection ", user_packet.remote_address.ip(), ":", user_packet.remote_address.port(), " reason: ", String::from_utf8_lossy(&user_packet.data));
+					if let Some(session) = state.sessions.end(&user_packet.remote_address) {
+						log_info!("Player '", session.username, "' left the server.");
+						state.chat.player_left(&user_packet.remote_address);
+						if session.state == SessionState::InGame {
+							Chat::broadcast_system(&network, &state.sessions, &format!("{} left the game.", session.username));
+						}
+					}
+				}
+			}
 		}
 	}
 }
@@ -98,12 +177,16 @@ fn get_packet_content_iterator(data: &Vec<u8>) -> EhResult<(u32, CustomIterator)
 }
 
 fn handle_user_packet(
-	server: &mut ServerInstance,
+	network: &NetworkThread,
 	address: SocketAddr,
 	data: Vec<u8>,
-	world: &mut World,
-	extra_data: &mut ExtraDataManager,
+	state: &mut GameState,
+	commands: &CommandRegistry,
 ) {
+	let session = unwrap_or_return!(state.sessions.get_active(&address), {
+		log_warn!("Ignoring packet from ", address.ip(), ":", address.port(), ", there is no active player session for it.");
+	});
+	log_debug!("Packet from player '", session.username, "'");
 	let (packet_id, mut iterator) = unwrap_or_print_return!(
 		get_packet_content_iterator(&data).wrap(ex!("While reading LW header of packet"))
 	);
@@ -113,16 +196,48 @@ fn handle_user_packet(
 		Some(PacketIDs::ConnectionEstablished) => {
 			log_info!("[UserPacket] Type: ConnectionEstablishedPacket");
 			unwrap_or_print_return!(ConnectionEstablished::parse(iterator).wrap(ex!("While parsing ConnectionEstablished packet")));
+			if !state.sessions.advance(&address, SessionState::Connecting, SessionState::LoadingWorld) {
+				return;
+			}
 			
 			//Respond with world packet:
 			
-			let world_initialization_packet = WorldInitializationPacket::simple(world);
+			let username = &state.sessions.get(&address).unwrap().username;
+			let world_initialization_packet = WorldInitializationPacket::simple(&mut state.world, username);
 			
 			let mut packet_buffer = Vec::new();
 			world_initialization_packet.write(&mut packet_buffer);
 			log_debug!("The packet about to be sent is ", packet_buffer.len(), " bytes long");
 			
-			server.send_to(address, packet_buffer);
+			//Same channel as the world updates, so that none of them can overtake the world:
+			network.send_to(address, packet_buffer);
+		}
+		Some(PacketIDs::ClientLoadedWorld) => {
+			log_info!("[UserPacket] Type: ClientLoadedWorldPacket");
+			unwrap_or_print_return!(ClientLoadedWorld::parse(iterator).wrap(ex!("While parsing ClientLoadedWorld packet")));
+			if state.sessions.advance(&address, SessionState::LoadingWorld, SessionState::InGame) {
+				//Changes made while the world was loading, in the order they happened:
+				for update in state.sessions.take_pending_world_updates(&address) {
+					network.send_to(address, update);
+				}
+				let username = &state.sessions.get(&address).unwrap().username;
+				Chat::broadcast_system(network, &state.sessions, &format!("{} joined the game.", username));
+			}
+		}
+		Some(PacketIDs::ChatMessageSent) => {
+			log_info!("[UserPacket] Type: ChatMessageSentPacket");
+			let packet = unwrap_or_print_return!(ChatMessageSent::parse(iterator).wrap(ex!("While parsing ChatMessageSent packet")));
+			state.chat.handle_message(network, &state.sessions, address, packet);
+		}
+		Some(PacketIDs::RunCommand) => {
+			log_info!("[UserPacket] Type: RunCommandPacket");
+			let packet = unwrap_or_print_return!(RunCommand::parse(iterator).wrap(ex!("While parsing RunCommand packet")));
+			commands.execute(network, state, address, &packet.command);
+		}
+		Some(PacketIDs::BuildingRequest) => {
+			log_info!("[UserPacket] Type: BuildingRequestPacket");
+			let request = unwrap_or_print_return!(BuildingRequest::parse(iterator).wrap(ex!("While parsing BuildingRequest packet")));
+			building::handle_request(network, state, address, request);
 		}
 		Some(PacketIDs::PlayerPosition) => {
 			log_info!("[UserPacket] Type: PlayerPositionPacket");
@@ -131,12 +246,12 @@ fn handle_user_packet(
 		Some(PacketIDs::ExtraDataRequest) => {
 			log_info!("[UserPacket] Type: ExtraDataRequestPacket");
 			let request = unwrap_or_print_return!(ExtraDataRequest::parse(iterator).wrap(ex!("While parsing ExtraDataRequest packet")));
-			extra_data.handle_request(request, server, address);
+			state.extra_data.handle_request(request, network, address);
 		}
 		Some(PacketIDs::ExtraDataChange) => {
 			log_info!("[UserPacket] Type: ExtraDataChangePacket");
 			let request = unwrap_or_print_return!(ExtraDataChange::parse(iterator).wrap(ex!("While parsing ExtraDataChange packet")));
-			extra_data.handle_change(request, server, address);
+			state.extra_data.handle_change(request, network, address);
 		}
 		_ => {
 			log_warn!("Warning: Received client packet with unknown type ", packet_id);
@@ -146,7 +261,7 @@ fn handle_user_packet(
 }
 
 fn handle_discovery(
-	server: &ServerInstance,
+	network: &NetworkThread,
 	remote_address: SocketAddr,
 	data: Vec<u8>,
 ) {
@@ -165,19 +280,32 @@ fn handle_discovery(
 	);
 	response.write(&mut result_buffer);
 	
-	server.answer_discovery(&remote_address, &result_buffer[..]);
+	network.answer_discovery(&remote_address, &result_buffer[..]);
 }
 
 fn handle_connect(
-	server: &ServerInstance,
+	network: &NetworkThread,
 	remote_address: SocketAddr,
 	data: Vec<u8>,
+	state: &mut GameState,
 ) {
 	let mut iterator = CustomIterator::borrow(&data[..]);
 	unwrap_or_print_return!(ConnectionApproval::validate_packet_id(&mut iterator).wrap(ex!("While validating ConnectionApproval packet ID")));
-	unwrap_or_print_return!(ConnectionApproval::parse(iterator).wrap(ex!("While parsing ConnectionApproval packet")));
+	let approval = unwrap_or_print_return!(ConnectionApproval::parse(iterator).wrap(ex!("While parsing ConnectionApproval packet")));
+	
+	if let Some(reason) = state.access_lists.check(&remote_address.ip(), &approval.username) {
+		log_info!("Refusing '", approval.username, "' from ", remote_address.ip(), ": ", reason);
+		network.reject_connect(&remote_address, &reason);
+		return;
+	}
+	if let Some(reason) = state.sessions.check_username(&approval.username) {
+		log_info!("Refusing '", approval.username, "' from ", remote_address.ip(), ": ", reason);
+		network.reject_connect(&remote_address, &reason);
+		return;
+	}
+	state.sessions.begin(remote_address, approval);
 	
 	//Send answer:
 	
-	server.answer_connect(&remote_address);
+	network.answer_connect(&remote_address);
 }
@@ -212,6 +212,13 @@ pub fn write_array_auto(buffer: &mut Vec<u8>, value: u32) {
 
 //Binary:
 
+pub(crate) fn write_binary_auto(buffer: &mut Vec<u8>, value: Option<&[u8]>) {
+	match value {
+		Some(value) => write_binary(buffer, value),
+		None => write_null(buffer),
+	}
+}
+
 pub(crate) fn write_binary(buffer: &mut Vec<u8>, value: &[u8]) {
 	let length = value.len();
 	match length {
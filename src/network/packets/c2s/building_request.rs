@@ -0,0 +1,192 @@
+use crate::prelude::*;
+use crate::network::packets::packet_tools::*;
+
+use crate::files::world_data::world_structs::{ComponentAddress, PegAddress, WireAddress};
+use crate::network::message_pack::reader as mp_reader;
+use crate::network::packets::packet_ids::PacketIDs;
+use crate::util::custom_iterator::CustomIterator;
+
+//Each peg allocates a circuit state, larger amounts are refused before they reach the world:
+const MAX_PEG_AMOUNT: u32 = 4096;
+
+//A single change to the world, the server answers each one with a BuildActionReceipt:
+pub struct BuildingRequest {
+	//Chosen by the client, to match the receipt to the request:
+	pub request_id: u32,
+	pub action: BuildAction,
+}
+
+pub enum BuildAction {
+	//The server has no component definitions, so the client tells how many pegs the component has:
+	PlaceComponent {
+		parent: ComponentAddress,
+		type_id: u16,
+		position: (i32, i32, i32),
+		alignment: (f32, f32, f32, f32),
+		input_count: u32,
+		output_count: u32,
+		custom_data: Option<Vec<u8>>,
+	},
+	//Children and wires connected to the component get deleted too:
+	DeleteComponent {
+		address: ComponentAddress,
+	},
+	MoveComponent {
+		address: ComponentAddress,
+		parent: ComponentAddress,
+		position: (i32, i32, i32),
+		alignment: (f32, f32, f32, f32),
+	},
+	EditCustomData {
+		address: ComponentAddress,
+		custom_data: Option<Vec<u8>>,
+	},
+	PlaceWire {
+		peg_a: PegAddress,
+		peg_b: PegAddress,
+		rotation: f32,
+	},
+	DeleteWire {
+		address: WireAddress,
+	},
+}
+
+impl BuildingRequest {
+	pub fn validate_packet_id(iterator: &mut CustomIterator) -> EhResult<()>{
+		expect_packet_id!(iterator, "building request", PacketIDs::BuildingRequest);
+		Ok(())
+	}
+	
+	pub fn parse(mut iterator: CustomIterator) -> EhResult<BuildingRequest> {
+		let iterator = &mut iterator;
+		
+		expect_array!(iterator, "BuildingRequest", "main content", 2);
+		let request_id = mp_reader::read_u32(iterator).wrap(ex!("While reading BuildingRequest packet's request id"))?;
+		
+		//Union of the actions, the type index followed by its fields:
+		expect_array!(iterator, "BuildingRequest", "action union", 2);
+		let action_type = mp_reader::read_u32(iterator).wrap(ex!("While reading BuildingRequest packet's action type"))?;
+		let action = match action_type {
+			0 => {
+				expect_array!(iterator, "BuildingRequest", "PlaceComponent", 7);
+				let parent = read_component_address(iterator).wrap(ex!("While reading PlaceComponent parent"))?;
+				let type_id = mp_reader::read_u16(iterator).wrap(ex!("While reading PlaceComponent type id"))?;
+				let position = read_position(iterator).wrap(ex!("While reading PlaceComponent position"))?;
+				let alignment = read_alignment(iterator).wrap(ex!("While reading PlaceComponent alignment"))?;
+				let input_count = mp_reader::read_u32(iterator).wrap(ex!("While reading PlaceComponent input amount"))?;
+				let output_count = mp_reader::read_u32(iterator).wrap(ex!("While reading PlaceComponent output amount"))?;
+				if input_count > MAX_PEG_AMOUNT || output_count > MAX_PEG_AMOUNT {
+					return exception!("PlaceComponent has too many pegs: ", input_count, " inputs and ", output_count, " outputs, at most ", MAX_PEG_AMOUNT, " each are allowed");
+				}
+				BuildAction::PlaceComponent {
+					parent,
+					type_id,
+					position,
+					alignment,
+					input_count,
+					output_count,
+					custom_data: read_custom_data(iterator).wrap(ex!("While reading PlaceComponent custom data"))?,
+				}
+			}
+			1 => {
+				expect_array!(iterator, "BuildingRequest", "DeleteComponent", 1);
+				BuildAction::DeleteComponent {
+					address: read_component_address(iterator).wrap(ex!("While reading DeleteComponent address"))?,
+				}
+			}
+			2 => {
+				expect_array!(iterator, "BuildingRequest", "MoveComponent", 4);
+				BuildAction::MoveComponent {
+					address: read_component_address(iterator).wrap(ex!("While reading MoveComponent address"))?,
+					parent: read_component_address(iterator).wrap(ex!("While reading MoveComponent parent"))?,
+					position: read_position(iterator).wrap(ex!("While reading MoveComponent position"))?,
+					alignment: read_alignment(iterator).wrap(ex!("While reading MoveComponent alignment"))?,
+				}
+			}
+			3 => {
+				expect_array!(iterator, "BuildingRequest", "EditCustomData", 2);
+				BuildAction::EditCustomData {
+					address: read_component_address(iterator).wrap(ex!("While reading EditCustomData address"))?,
+					custom_data: read_custom_data(iterator).wrap(ex!("While reading EditCustomData custom data"))?,
+				}
+			}
+			4 => {
+				expect_array!(iterator, "BuildingRequest", "PlaceWire", 3);
+				BuildAction::PlaceWire {
+					peg_a: read_peg_address(iterator).wrap(ex!("While reading PlaceWire peg A"))?,
+					peg_b: read_peg_address(iterator).wrap(ex!("While reading PlaceWire peg B"))?,
+					rotation: mp_reader::read_f32(iterator).wrap(ex!("While reading PlaceWire rotation"))?,
+				}
+			}
+			5 => {
+				expect_array!(iterator, "BuildingRequest", "DeleteWire", 1);
+				expect_array!(iterator, "BuildingRequest", "DeleteWire address wrapper", 1);
+				BuildAction::DeleteWire {
+					address: WireAddress {
+						id: mp_reader::read_u32(iterator).wrap(ex!("While reading DeleteWire address"))?,
+					},
+				}
+			}
+			_ => return exception!("Unknown build action type ", action_type),
+		};
+		
+		expect_end_of_packet!(iterator, "BuildingRequest");
+		
+		Ok(BuildingRequest {
+			request_id,
+			action,
+		})
+	}
+}
+
+fn read_component_address(iterator: &mut CustomIterator) -> EhResult<ComponentAddress> {
+	expect_array!(iterator, "BuildingRequest", "component address wrapper", 1);
+	let id = mp_reader::read_u32(iterator).wrap(ex!("While reading component address"))?;
+	Ok(ComponentAddress {
+		id,
+	})
+}
+
+//Same layout as in the world initialization packet, the type is 0 for inputs and 1 for outputs:
+fn read_peg_address(iterator: &mut CustomIterator) -> EhResult<PegAddress> {
+	expect_array!(iterator, "BuildingRequest", "peg address", 2);
+	let peg_type = mp_reader::read_u32(iterator).wrap(ex!("While reading peg type"))?;
+	if peg_type > 1 {
+		return exception!("Unknown peg type ", peg_type);
+	}
+	expect_array!(iterator, "BuildingRequest", "peg address content", 2);
+	let component_address = read_component_address(iterator).wrap(ex!("While reading peg component address"))?;
+	let peg_index = mp_reader::read_u32(iterator).wrap(ex!("While reading peg index"))?;
+	Ok(PegAddress {
+		is_input: peg_type == 0,
+		component_address,
+		peg_index,
+	})
+}
+
+fn read_position(iterator: &mut CustomIterator) -> EhResult<(i32, i32, i32)> {
+	expect_array!(iterator, "BuildingRequest", "position", 3);
+	Ok((
+		mp_reader::read_i32(iterator).wrap(ex!("While reading position X"))?,
+		mp_reader::read_i32(iterator).wrap(ex!("While reading position Y"))?,
+		mp_reader::read_i32(iterator).wrap(ex!("While reading position Z"))?,
+	))
+}
+
+fn read_alignment(iterator: &mut CustomIterator) -> EhResult<(f32, f32, f32, f32)> {
+	expect_array!(iterator, "BuildingRequest", "alignment", 4);
+	Ok((
+		mp_reader::read_f32(iterator).wrap(ex!("While reading alignment X"))?,
+		mp_reader::read_f32(iterator).wrap(ex!("While reading alignment Y"))?,
+		mp_reader::read_f32(iterator).wrap(ex!("While reading alignment Z"))?,
+		mp_reader::read_f32(iterator).wrap(ex!("While reading alignment W"))?,
+	))
+}
+
+//Nil is sent for components without custom data:
+fn read_custom_data(iterator: &mut CustomIterator) -> EhResult<Option<Vec<u8>>> {
+	if mp_reader::check_null(iterator).wrap(ex!("While checking for missing custom data"))? {
+		return Ok(None);
+	}
+	Ok(Some(mp_reader::read_bytes(iterator).wrap(ex!("While reading custom data bytes"))?))
+}
@@ -0,0 +1,30 @@
+use crate::prelude::*;
+use crate::network::packets::packet_tools::*;
+
+use crate::network::message_pack::reader as mp_reader;
+use crate::network::packets::packet_ids::PacketIDs;
+use crate::util::custom_iterator::CustomIterator;
+
+pub struct ChatMessageSent {
+	pub message: String,
+}
+
+impl ChatMessageSent {
+	pub fn validate_packet_id(iterator: &mut CustomIterator) -> EhResult<()>{
+		expect_packet_id!(iterator, "chat message sent", PacketIDs::ChatMessageSent);
+		Ok(())
+	}
+	
+	pub fn parse(mut iterator: CustomIterator) -> EhResult<ChatMessageSent> {
+		let iterator = &mut iterator;
+		
+		expect_array!(iterator, "ChatMessageSent", "main content", 1);
+		let message = mp_reader::read_string(iterator).wrap(ex!("While reading ChatMessageSent packet's message"))?;
+		
+		expect_end_of_packet!(iterator, "ChatMessageSent");
+		
+		Ok(ChatMessageSent {
+			message,
+		})
+	}
+}
@@ -0,0 +1,29 @@
+use crate::prelude::*;
+use crate::network::packets::packet_tools::*;
+
+use crate::network::message_pack::reader as mp_reader;
+use crate::network::packets::packet_ids::PacketIDs;
+use crate::util::custom_iterator::CustomIterator;
+
+pub struct ClientLoadedWorld {
+}
+
+impl ClientLoadedWorld {
+	pub fn validate_packet_id(iterator: &mut CustomIterator) -> EhResult<()>{
+		expect_packet_id!(iterator, "client loaded world", PacketIDs::ClientLoadedWorld);
+		Ok(())
+	}
+	
+	pub fn parse(mut iterator: CustomIterator) -> EhResult<ClientLoadedWorld> {
+		let iterator = &mut iterator;
+		expect_array!(iterator, "ClientLoadedWorld", "main content", 1);
+		let number = mp_reader::read_u32(iterator).wrap(ex!("While parsing ClientLoadedWorld packet's dummy value"))?;
+		if number != 0 {
+			return exception!("Expected ClientLoadedWorld expected integer of value 0, got: ", number);
+		}
+		
+		expect_end_of_packet!(iterator, "ClientLoadedWorld");
+		
+		Ok(ClientLoadedWorld {})
+	}
+}
@@ -0,0 +1,30 @@
+use crate::prelude::*;
+use crate::network::packets::packet_tools::*;
+
+use crate::network::message_pack::reader as mp_reader;
+use crate::network::packets::packet_ids::PacketIDs;
+use crate::util::custom_iterator::CustomIterator;
+
+pub struct RunCommand {
+	pub command: String,
+}
+
+impl RunCommand {
+	pub fn validate_packet_id(iterator: &mut CustomIterator) -> EhResult<()>{
+		expect_packet_id!(iterator, "run command", PacketIDs::RunCommand);
+		Ok(())
+	}
+	
+	pub fn parse(mut iterator: CustomIterator) -> EhResult<RunCommand> {
+		let iterator = &mut iterator;
+		
+		expect_array!(iterator, "RunCommand", "main content", 1);
+		let command = mp_reader::read_string(iterator).wrap(ex!("While reading RunCommand packet's command"))?;
+		
+		expect_end_of_packet!(iterator, "RunCommand");
+		
+		Ok(RunCommand {
+			command,
+		})
+	}
+}
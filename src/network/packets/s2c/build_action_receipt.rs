@@ -0,0 +1,28 @@
+use crate::network::message_pack::writer as mp_writer;
+use crate::network::packets::packet_ids::PacketIDs;
+
+//Answer to a BuildingRequest, telling the client whether its action got applied:
+pub struct BuildActionReceipt<'a> {
+	pub request_id: u32,
+	//Address the server gave to a placed component or wire:
+	pub created_address: Option<u32>,
+	//Why the action got refused, none if it got applied:
+	pub rejection_reason: Option<&'a str>,
+}
+
+impl<'a> BuildActionReceipt<'a> {
+	pub fn write(&self, buffer: &mut Vec<u8>) {
+		//Version:
+		mp_writer::write_int_auto(buffer, PacketIDs::BuildActionReceipt.id());
+		
+		//Data:
+		mp_writer::write_array_auto(buffer, 4);
+		mp_writer::write_int_auto(buffer, self.request_id);
+		mp_writer::write_bool(buffer, self.rejection_reason.is_none());
+		match self.created_address {
+			Some(address) => mp_writer::write_int_auto(buffer, address),
+			None => mp_writer::write_null(buffer),
+		}
+		mp_writer::write_string_auto(buffer, self.rejection_reason);
+	}
+}
@@ -0,0 +1,28 @@
+use crate::files::world_data::world_structs::Color24;
+use crate::network::message_pack::writer as mp_writer;
+use crate::network::packets::packet_ids::PacketIDs;
+
+pub struct ChatMessageBroadcast<'a> {
+	pub sender: &'a str,
+	pub message: &'a str,
+	pub sender_color: Color24,
+}
+
+impl<'a> ChatMessageBroadcast<'a> {
+	pub fn write(&self, buffer: &mut Vec<u8>) {
+		//Version:
+		mp_writer::write_int_auto(buffer, PacketIDs::ChatMessageBroadcast.id());
+		
+		//Data:
+		mp_writer::write_array_auto(buffer, 1);
+		//ChatMessage:
+		mp_writer::write_array_auto(buffer, 3);
+		mp_writer::write_string_auto(buffer, Some(self.sender));
+		mp_writer::write_string_auto(buffer, Some(self.message));
+		//Color24:
+		mp_writer::write_array_auto(buffer, 3);
+		mp_writer::write_int_auto(buffer, self.sender_color.r as u32);
+		mp_writer::write_int_auto(buffer, self.sender_color.g as u32);
+		mp_writer::write_int_auto(buffer, self.sender_color.b as u32);
+	}
+}
@@ -0,0 +1,18 @@
+use crate::network::message_pack::writer as mp_writer;
+use crate::network::packets::packet_ids::PacketIDs;
+
+//Text for the console of a client, like replies to commands:
+pub struct DebugMessage<'a> {
+	pub message: &'a str,
+}
+
+impl<'a> DebugMessage<'a> {
+	pub fn write(&self, buffer: &mut Vec<u8>) {
+		//Version:
+		mp_writer::write_int_auto(buffer, PacketIDs::DebugMessage.id());
+		
+		//Data:
+		mp_writer::write_array_auto(buffer, 1);
+		mp_writer::write_string_auto(buffer, Some(self.message));
+	}
+}
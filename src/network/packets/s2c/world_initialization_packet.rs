@@ -1,15 +1,17 @@
-use crate::files::world_data::world_structs::World;
+use crate::files::world_data::world_structs::{Component, PegAddress, Wire, WireAddress, World};
 use crate::network::message_pack::writer as mp_writer;
 use crate::network::packets::packet_ids::PacketIDs;
 
 pub struct WorldInitializationPacket<'a> {
 	world: &'a mut World,
+	player_name: &'a str,
 }
 
 impl<'a> WorldInitializationPacket<'a> {
-	pub fn simple(world: &mut World) -> WorldInitializationPacket {
+	pub fn simple(world: &'a mut World, player_name: &'a str) -> WorldInitializationPacket<'a> {
 		WorldInitializationPacket {
-			world
+			world,
+			player_name,
 		}
 	}
 	
@@ -40,84 +42,15 @@ impl<'a> WorldInitializationPacket<'a> {
 			//Components:
 			mp_writer::write_array_auto(buffer, self.world.components.len() as u32); //No components
 			for component in self.world.components.iter() {
-				//Tuple declaration:
-				mp_writer::write_array_auto(buffer, 2);
-				mp_writer::write_array_auto(buffer, 1); //To wrap the component address...
-				mp_writer::write_int_auto(buffer, component.address.id);
-				
-				mp_writer::write_array_auto(buffer, 7);
-				
-				//Type:
-				mp_writer::write_array_auto(buffer, 1); //To wrap...
-				mp_writer::write_int_16(buffer, component.type_id);
-				
-				//Inputs:
-				mp_writer::write_array_auto(buffer, component.inputs.len() as u32);
-				for state in component.inputs.iter() {
-					mp_writer::write_array_auto(buffer, 1); //To wrap...
-					mp_writer::write_int_auto(buffer, *state);
-				}
-				
-				//Outputs:
-				mp_writer::write_array_auto(buffer, component.outputs.len() as u32);
-				for state in component.outputs.iter() {
-					mp_writer::write_array_auto(buffer, 1); //To wrap...
-					mp_writer::write_int_auto(buffer, *state);
-				}
-				
-				//CustomData:
-				//TODO: NULLABLE
-				mp_writer::write_binary(buffer, &component.custom_data);
-				
-				//Parent:
-				mp_writer::write_array_auto(buffer, 1); //To wrap...
-				mp_writer::write_int_auto(buffer, component.parent.id);
-				
-				//RelativePosition:
-				mp_writer::write_array_auto(buffer, 3);
-				mp_writer::write_i32(buffer, component.relative_position.0);
-				mp_writer::write_i32(buffer, component.relative_position.1);
-				mp_writer::write_i32(buffer, component.relative_position.2);
-				
-				//RelativeAlignment:
-				mp_writer::write_array_auto(buffer, 4);
-				mp_writer::write_float_auto(buffer, component.relative_alignment.0);
-				mp_writer::write_float_auto(buffer, component.relative_alignment.1);
-				mp_writer::write_float_auto(buffer, component.relative_alignment.2);
-				mp_writer::write_float_auto(buffer, component.relative_alignment.3);
+				write_component(buffer, component);
 			}
 			
 			//Wires:
 			mp_writer::write_map_auto(buffer, self.world.wires.len() as u32);
-			let mut index = 1;
 			for wire in self.world.wires.iter() {
-				mp_writer::write_array_auto(buffer, 1); //To wrap the wire address...
-				mp_writer::write_int_auto(buffer, index);
-				index += 1;
-				
-				mp_writer::write_array_auto(buffer, 4);
-				
-				//Peg 1:
-				mp_writer::write_array_auto(buffer, 2);
-				mp_writer::write_int_auto(buffer, (!wire.peg_a.is_input) as u32);
-				mp_writer::write_array_auto(buffer, 2);
-				mp_writer::write_array_auto(buffer, 1); //To wrap...
-				mp_writer::write_int_auto(buffer, wire.peg_a.component_address.id);
-				mp_writer::write_int_auto(buffer, wire.peg_a.peg_index);
-				
-				//Peg 2:
-				mp_writer::write_array_auto(buffer, 2);
-				mp_writer::write_int_auto(buffer, (!wire.peg_b.is_input) as u32);
-				mp_writer::write_array_auto(buffer, 2);
-				mp_writer::write_array_auto(buffer, 1); //To wrap...
-				mp_writer::write_int_auto(buffer, wire.peg_b.component_address.id);
-				mp_writer::write_int_auto(buffer, wire.peg_b.peg_index);
-				
-				//Circuit state:
-				mp_writer::write_int_auto(buffer, wire.circuit_state_id);
-				
-				//Rotation:
-				mp_writer::write_float_auto(buffer, wire.rotation);
+				//Key and value of the map:
+				write_wire_address(buffer, &wire.address);
+				write_wire(buffer, wire);
 			}
 			
 			//PlayerPosition:
@@ -156,7 +89,86 @@ impl<'a> WorldInitializationPacket<'a> {
 			mp_writer::write_null(buffer); //No clue if a "null" hotbar works, but I think that means default.
 			
 			//PlayerName:
-			mp_writer::write_string_auto(buffer, Some("EpicUsername"));
+			mp_writer::write_string_auto(buffer, Some(self.player_name));
 		}
 	}
 }
+
+//Shared with world updates, which announce single components:
+pub fn write_component(buffer: &mut Vec<u8>, component: &Component) {
+	//Tuple declaration:
+	mp_writer::write_array_auto(buffer, 2);
+	mp_writer::write_array_auto(buffer, 1); //To wrap the component address...
+	mp_writer::write_int_auto(buffer, component.address.id);
+	
+	mp_writer::write_array_auto(buffer, 7);
+	
+	//Type:
+	mp_writer::write_array_auto(buffer, 1); //To wrap...
+	mp_writer::write_int_16(buffer, component.type_id);
+	
+	//Inputs:
+	mp_writer::write_array_auto(buffer, component.inputs.len() as u32);
+	for state in component.inputs.iter() {
+		mp_writer::write_array_auto(buffer, 1); //To wrap...
+		mp_writer::write_int_auto(buffer, *state);
+	}
+	
+	//Outputs:
+	mp_writer::write_array_auto(buffer, component.outputs.len() as u32);
+	for state in component.outputs.iter() {
+		mp_writer::write_array_auto(buffer, 1); //To wrap...
+		mp_writer::write_int_auto(buffer, *state);
+	}
+	
+	//CustomData:
+	mp_writer::write_binary_auto(buffer, component.custom_data.as_deref());
+	
+	//Parent:
+	mp_writer::write_array_auto(buffer, 1); //To wrap...
+	mp_writer::write_int_auto(buffer, component.parent.id);
+	
+	//RelativePosition:
+	mp_writer::write_array_auto(buffer, 3);
+	mp_writer::write_i32(buffer, component.relative_position.0);
+	mp_writer::write_i32(buffer, component.relative_position.1);
+	mp_writer::write_i32(buffer, component.relative_position.2);
+	
+	//RelativeAlignment:
+	mp_writer::write_array_auto(buffer, 4);
+	mp_writer::write_float_auto(buffer, component.relative_alignment.0);
+	mp_writer::write_float_auto(buffer, component.relative_alignment.1);
+	mp_writer::write_float_auto(buffer, component.relative_alignment.2);
+	mp_writer::write_float_auto(buffer, component.relative_alignment.3);
+}
+
+pub fn write_wire_address(buffer: &mut Vec<u8>, address: &WireAddress) {
+	mp_writer::write_array_auto(buffer, 1); //To wrap the wire address...
+	mp_writer::write_int_auto(buffer, address.id);
+}
+
+//Shared with world updates, which announce single wires. Does not contain the address:
+pub fn write_wire(buffer: &mut Vec<u8>, wire: &Wire) {
+	mp_writer::write_array_auto(buffer, 4);
+	
+	//Peg 1:
+	write_peg_address(buffer, &wire.peg_a);
+	
+	//Peg 2:
+	write_peg_address(buffer, &wire.peg_b);
+	
+	//Circuit state:
+	mp_writer::write_int_auto(buffer, wire.circuit_state_id);
+	
+	//Rotation:
+	mp_writer::write_float_auto(buffer, wire.rotation);
+}
+
+pub fn write_peg_address(buffer: &mut Vec<u8>, peg_address: &PegAddress) {
+	mp_writer::write_array_auto(buffer, 2);
+	mp_writer::write_int_auto(buffer, (!peg_address.is_input) as u32);
+	mp_writer::write_array_auto(buffer, 2);
+	mp_writer::write_array_auto(buffer, 1); //To wrap...
+	mp_writer::write_int_auto(buffer, peg_address.component_address.id);
+	mp_writer::write_int_auto(buffer, peg_address.peg_index);
+}
@@ -0,0 +1,88 @@
+use crate::files::world_data::world_structs::{Component, ComponentAddress, Wire, WireAddress};
+use crate::network::message_pack::writer as mp_writer;
+use crate::network::packets::packet_ids::PacketIDs;
+use crate::network::packets::s2c::world_initialization_packet::{write_component, write_wire, write_wire_address};
+
+//Result of an applied build action, as the other players have to apply it:
+pub enum WorldChange {
+	ComponentPlaced(Component),
+	ComponentDeleted(ComponentAddress),
+	ComponentMoved {
+		address: ComponentAddress,
+		parent: ComponentAddress,
+		position: (i32, i32, i32),
+		alignment: (f32, f32, f32, f32),
+	},
+	CustomDataChanged {
+		address: ComponentAddress,
+		custom_data: Option<Vec<u8>>,
+	},
+	WirePlaced(Wire),
+	WireDeleted(WireAddress),
+}
+
+pub struct WorldUpdate<'a> {
+	pub changes: &'a [WorldChange],
+}
+
+impl<'a> WorldUpdate<'a> {
+	pub fn write(&self, buffer: &mut Vec<u8>) {
+		//Version:
+		mp_writer::write_int_auto(buffer, PacketIDs::WorldUpdate.id());
+		
+		//Data:
+		mp_writer::write_array_auto(buffer, 1);
+		mp_writer::write_array_auto(buffer, self.changes.len() as u32);
+		for change in self.changes.iter() {
+			//Union of the changes, the type index followed by its content:
+			mp_writer::write_array_auto(buffer, 2);
+			match change {
+				WorldChange::ComponentPlaced(component) => {
+					mp_writer::write_int_auto(buffer, 0);
+					write_component(buffer, component);
+				}
+				WorldChange::ComponentDeleted(address) => {
+					mp_writer::write_int_auto(buffer, 1);
+					write_component_address(buffer, address);
+				}
+				WorldChange::ComponentMoved { address, parent, position, alignment } => {
+					mp_writer::write_int_auto(buffer, 2);
+					mp_writer::write_array_auto(buffer, 4);
+					write_component_address(buffer, address);
+					write_component_address(buffer, parent);
+					mp_writer::write_array_auto(buffer, 3);
+					mp_writer::write_i32(buffer, position.0);
+					mp_writer::write_i32(buffer, position.1);
+					mp_writer::write_i32(buffer, position.2);
+					mp_writer::write_array_auto(buffer, 4);
+					mp_writer::write_float_auto(buffer, alignment.0);
+					mp_writer::write_float_auto(buffer, alignment.1);
+					mp_writer::write_float_auto(buffer, alignment.2);
+					mp_writer::write_float_auto(buffer, alignment.3);
+				}
+				WorldChange::CustomDataChanged { address, custom_data } => {
+					mp_writer::write_int_auto(buffer, 3);
+					mp_writer::write_array_auto(buffer, 2);
+					write_component_address(buffer, address);
+					mp_writer::write_binary_auto(buffer, custom_data.as_deref());
+				}
+				WorldChange::WirePlaced(wire) => {
+					mp_writer::write_int_auto(buffer, 4);
+					//Tuple of address and wire, like components:
+					mp_writer::write_array_auto(buffer, 2);
+					write_wire_address(buffer, &wire.address);
+					write_wire(buffer, wire);
+				}
+				WorldChange::WireDeleted(address) => {
+					mp_writer::write_int_auto(buffer, 5);
+					write_wire_address(buffer, address);
+				}
+			}
+		}
+	}
+}
+
+fn write_component_address(buffer: &mut Vec<u8>, address: &ComponentAddress) {
+	mp_writer::write_array_auto(buffer, 1); //To wrap...
+	mp_writer::write_int_auto(buffer, address.id);
+}
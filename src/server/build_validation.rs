@@ -0,0 +1,129 @@
+use crate::files::world_data::world_structs::{ComponentAddress, PegAddress, World};
+use crate::network::packets::c2s::building_request::BuildAction;
+
+pub struct BuildLimits {
+	//Custom data is stored and sent to every player as is, so it may not grow without bound:
+	pub max_custom_data_size: usize,
+	//Each peg allocates a circuit state:
+	pub max_pegs_per_component: u32,
+	//Positions are in fixed point, this keeps components near the world:
+	pub max_position_distance: i32,
+}
+
+impl Default for BuildLimits {
+	fn default() -> Self {
+		Self {
+			max_custom_data_size: 64 * 1024,
+			max_pegs_per_component: 256,
+			max_position_distance: 1_000_000_000,
+		}
+	}
+}
+
+//Checks a requested build action against the world, before it gets applied.
+//Returns the reason to send back to the client, if the action may not be applied.
+pub fn validate(world: &World, limits: &BuildLimits, action: &BuildAction) -> Result<(), String> {
+	match action {
+		BuildAction::PlaceComponent { parent, type_id, position, alignment, input_count, output_count, custom_data } => {
+			check_parent(world, parent)?;
+			if !world.component_id_map.contains_key(type_id) {
+				return Err(format!("Component type {} is not known to this world", type_id));
+			}
+			check_placement(limits, position, alignment)?;
+			if input_count.saturating_add(*output_count) > limits.max_pegs_per_component {
+				return Err(format!("Components may have at most {} pegs", limits.max_pegs_per_component));
+			}
+			check_custom_data(limits, custom_data.as_deref())
+		}
+		BuildAction::DeleteComponent { address } => {
+			check_component(world, address)
+		}
+		BuildAction::MoveComponent { address, parent, position, alignment } => {
+			check_component(world, address)?;
+			check_parent(world, parent)?;
+			if world.collect_subtree(address).contains(parent) {
+				return Err(format!("Component {} cannot be moved into itself or one of its children", address.id));
+			}
+			check_placement(limits, position, alignment)
+		}
+		BuildAction::EditCustomData { address, custom_data } => {
+			check_component(world, address)?;
+			check_custom_data(limits, custom_data.as_deref())
+		}
+		BuildAction::PlaceWire { peg_a, peg_b, rotation } => {
+			check_peg(world, peg_a)?;
+			check_peg(world, peg_b)?;
+			if peg_a == peg_b {
+				return Err(String::from("A wire cannot connect a peg with itself"));
+			}
+			if !peg_a.is_input && !peg_b.is_input {
+				return Err(String::from("A wire cannot connect two outputs"));
+			}
+			let is_duplicate = world.wires.iter().any(|wire| {
+				(wire.peg_a == *peg_a && wire.peg_b == *peg_b) || (wire.peg_a == *peg_b && wire.peg_b == *peg_a)
+			});
+			if is_duplicate {
+				return Err(String::from("These pegs are already connected"));
+			}
+			if !rotation.is_finite() {
+				return Err(String::from("The wire rotation is not a number"));
+			}
+			Ok(())
+		}
+		BuildAction::DeleteWire { address } => {
+			if world.find_wire(address).is_none() {
+				return Err(format!("There is no wire with address {}", address.id));
+			}
+			Ok(())
+		}
+	}
+}
+
+fn check_component(world: &World, address: &ComponentAddress) -> Result<(), String> {
+	if world.find_component(address).is_none() {
+		return Err(format!("There is no component with address {}", address.id));
+	}
+	Ok(())
+}
+
+fn check_parent(world: &World, parent: &ComponentAddress) -> Result<(), String> {
+	if *parent != ComponentAddress::ROOT && world.find_component(parent).is_none() {
+		return Err(format!("There is no parent component with address {}", parent.id));
+	}
+	Ok(())
+}
+
+fn check_peg(world: &World, peg: &PegAddress) -> Result<(), String> {
+	check_component(world, &peg.component_address)?;
+	if world.find_peg_state(peg).is_none() {
+		let peg_type = if peg.is_input { "input" } else { "output" };
+		return Err(format!("Component {} has no {} with index {}", peg.component_address.id, peg_type, peg.peg_index));
+	}
+	Ok(())
+}
+
+fn check_placement(limits: &BuildLimits, position: &(i32, i32, i32), alignment: &(f32, f32, f32, f32)) -> Result<(), String> {
+	let is_out_of_bounds = [position.0, position.1, position.2].iter()
+		.any(|coordinate| coordinate.unsigned_abs() > limits.max_position_distance.unsigned_abs());
+	if is_out_of_bounds {
+		return Err(String::from("The position is too far away"));
+	}
+	//The alignment is a rotation quaternion, which has a length of 1:
+	let components = [alignment.0, alignment.1, alignment.2, alignment.3];
+	if components.iter().any(|component| !component.is_finite()) {
+		return Err(String::from("The alignment is not a number"));
+	}
+	let length = components.iter().map(|component| component * component).sum::<f32>().sqrt();
+	if (length - 1.0).abs() > 0.01 {
+		return Err(String::from("The alignment is not a rotation"));
+	}
+	Ok(())
+}
+
+fn check_custom_data(limits: &BuildLimits, custom_data: Option<&[u8]>) -> Result<(), String> {
+	let size = custom_data.map_or(0, |custom_data| custom_data.len());
+	if size > limits.max_custom_data_size {
+		return Err(format!("Custom data may be at most {} bytes, got {}", limits.max_custom_data_size, size));
+	}
+	Ok(())
+}
@@ -0,0 +1,137 @@
+use crate::prelude::*;
+
+use std::net::SocketAddr;
+
+use crate::files::world_data::world_structs::{Component, Wire, World};
+use crate::lidgren::network_thread::NetworkThread;
+use crate::network::packets::c2s::building_request::{BuildAction, BuildingRequest};
+use crate::network::packets::s2c::build_action_receipt::BuildActionReceipt;
+use crate::network::packets::s2c::world_update::{WorldChange, WorldUpdate};
+use crate::server::build_validation;
+use crate::server::game_state::GameState;
+use crate::server::player_sessions::SessionState;
+
+//Applies build actions of players to the world. The sender gets a receipt, everyone else the changes.
+pub fn handle_request(network: &NetworkThread, state: &mut GameState, sender: SocketAddr, request: BuildingRequest) {
+	let session = unwrap_or_return!(state.sessions.get_active(&sender), {
+		log_warn!("Dropping build request of ", sender.ip(), ":", sender.port(), ", there is no session for it.");
+	});
+	if session.state != SessionState::InGame {
+		log_warn!("Dropping build request of '", session.username, "', it did not load the world yet.");
+		return;
+	}
+	
+	if let Err(reason) = build_validation::validate(&state.world, &state.build_limits, &request.action) {
+		log_info!("Refused build request of '", session.username, "': ", reason);
+		let mut buffer = Vec::new();
+		BuildActionReceipt {
+			request_id: request.request_id,
+			created_address: None,
+			rejection_reason: Some(&reason),
+		}.write(&mut buffer);
+		network.send_to(sender, buffer);
+		return;
+	}
+	let (created_address, changes) = apply(&mut state.world, request.action);
+	let mut buffer = Vec::new();
+	BuildActionReceipt {
+		request_id: request.request_id,
+		created_address,
+		rejection_reason: None,
+	}.write(&mut buffer);
+	network.send_to(sender, buffer);
+	
+	let mut buffer = Vec::new();
+	WorldUpdate {
+		changes: &changes,
+	}.write(&mut buffer);
+	for session in state.sessions.all_mut() {
+		match session.state {
+			SessionState::InGame if session.address != sender => network.send_to(session.address, buffer.clone()),
+			//The world this player got is older than the change. It is sent on another channel and may still be
+			// on its way, so the update is held back until the player loaded the world:
+			SessionState::LoadingWorld => session.pending_world_updates.push(buffer.clone()),
+			_ => {}
+		}
+	}
+}
+
+//Returns the address of what got placed (if anything) and the resulting changes.
+//The action has to be validated before, all addresses it refers to exist:
+fn apply(world: &mut World, action: BuildAction) -> (Option<u32>, Vec<WorldChange>) {
+	match action {
+		BuildAction::PlaceComponent { parent, type_id, position, alignment, input_count, output_count, custom_data } => {
+			let address = world.allocate_component_address();
+			let inputs = (0..input_count).map(|_| allocate_circuit_state(world)).collect();
+			let outputs = (0..output_count).map(|_| allocate_circuit_state(world)).collect();
+			let component = Component {
+				address,
+				parent,
+				type_id,
+				relative_position: position,
+				relative_alignment: alignment,
+				inputs,
+				outputs,
+				custom_data,
+			};
+			world.components.push(component.clone());
+			(Some(address.id), vec![WorldChange::ComponentPlaced(component)])
+		}
+		BuildAction::DeleteComponent { address } => {
+			let deleted = world.collect_subtree(&address);
+			let mut changes = Vec::new();
+			world.wires.retain(|wire| {
+				let is_connected = deleted.contains(&wire.peg_a.component_address) || deleted.contains(&wire.peg_b.component_address);
+				if is_connected {
+					changes.push(WorldChange::WireDeleted(wire.address));
+				}
+				!is_connected
+			});
+			//Circuit states of the deleted pegs are not reused, there is no simulation yet to keep track of them:
+			world.components.retain(|component| {
+				let is_deleted = deleted.contains(&component.address);
+				if is_deleted {
+					changes.push(WorldChange::ComponentDeleted(component.address));
+				}
+				!is_deleted
+			});
+			(None, changes)
+		}
+		BuildAction::MoveComponent { address, parent, position, alignment } => {
+			let component = world.find_component_mut(&address).unwrap(); //Got validated.
+			component.parent = parent;
+			component.relative_position = position;
+			component.relative_alignment = alignment;
+			(None, vec![WorldChange::ComponentMoved { address, parent, position, alignment }])
+		}
+		BuildAction::EditCustomData { address, custom_data } => {
+			let component = world.find_component_mut(&address).unwrap(); //Got validated.
+			component.custom_data = custom_data.clone();
+			(None, vec![WorldChange::CustomDataChanged { address, custom_data }])
+		}
+		BuildAction::PlaceWire { peg_a, peg_b, rotation } => {
+			//The wire carries the state of the output it is connected to.
+			// Clusters of connected inputs are not merged, that is up to the simulation, once there is one:
+			let output_peg = if peg_b.is_input { &peg_a } else { &peg_b };
+			let circuit_state_id = world.find_peg_state(output_peg).unwrap(); //Got validated.
+			let wire = Wire {
+				address: world.allocate_wire_address(),
+				peg_a,
+				peg_b,
+				circuit_state_id,
+				rotation,
+			};
+			world.wires.push(wire.clone());
+			(Some(wire.address.id), vec![WorldChange::WirePlaced(wire)])
+		}
+		BuildAction::DeleteWire { address } => {
+			world.wires.retain(|wire| wire.address != address);
+			(None, vec![WorldChange::WireDeleted(address)])
+		}
+	}
+}
+
+fn allocate_circuit_state(world: &mut World) -> u32 {
+	world.circuit_states.push(false);
+	(world.circuit_states.len() - 1) as u32
+}
@@ -0,0 +1,118 @@
+use crate::prelude::*;
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use crate::files::world_data::world_structs::Color24;
+use crate::lidgren::network_thread::NetworkThread;
+use crate::lidgren::send_priority::SendPriority;
+use crate::network::packets::c2s::chat_message_sent::ChatMessageSent;
+use crate::network::packets::s2c::chat_message_broadcast::ChatMessageBroadcast;
+use crate::server::player_sessions::{PlayerSessions, SessionState};
+
+const SYSTEM_SENDER: &str = "Server";
+const SYSTEM_COLOR: Color24 = Color24 { r: 255, g: 200, b: 0 };
+//There is no player appearance yet, everyone gets the same color:
+const PLAYER_COLOR: Color24 = Color24 { r: 255, g: 255, b: 255 };
+
+pub struct ChatSettings {
+	//Longer messages are refused, counted in characters:
+	pub max_message_length: usize,
+	//Players have to wait this long between two messages:
+	pub min_message_interval: Duration,
+}
+
+impl Default for ChatSettings {
+	fn default() -> Self {
+		Self {
+			max_message_length: 256,
+			min_message_interval: Duration::from_millis(500),
+		}
+	}
+}
+
+//Forwards chat messages of players to everyone in game, and sends messages of the server itself.
+#[derive(Default)]
+pub struct Chat {
+	pub settings: ChatSettings,
+	last_message: HashMap<SocketAddr, Instant>,
+}
+
+impl Chat {
+	pub fn handle_message(&mut self, network: &NetworkThread, sessions: &PlayerSessions, sender: SocketAddr, packet: ChatMessageSent) {
+		let session = unwrap_or_return!(sessions.get_active(&sender), {
+			log_warn!("Dropping chat message of ", sender.ip(), ":", sender.port(), ", there is no session for it.");
+		});
+		if session.state != SessionState::InGame {
+			log_warn!("Dropping chat message of '", session.username, "', it did not load the world yet.");
+			return;
+		}
+		let message = match self.check_message(&sender, &packet.message) {
+			Ok(message) => message,
+			Err(reason) => {
+				Chat::send_system_to(network, sender, &reason);
+				return;
+			}
+		};
+		self.last_message.insert(sender, Instant::now());
+		log_info!("[Chat] <", session.username, "> ", message);
+		Chat::broadcast(network, sessions, &ChatMessageBroadcast {
+			sender: &session.username,
+			message: &message,
+			sender_color: PLAYER_COLOR,
+		});
+	}
+	
+	//Returns the message as it will be broadcast, or the reason why it is refused:
+	fn check_message(&self, sender: &SocketAddr, message: &str) -> Result<String, String> {
+		if let Some(last_message) = self.last_message.get(sender) {
+			if last_message.elapsed().lt(&self.settings.min_message_interval) {
+				return Err(String::from("You are sending messages too fast."));
+			}
+		}
+		//Control characters (like line breaks) could break the chat display of others:
+		let message: String = message.chars().filter(|character| !character.is_control()).collect();
+		let message = message.trim();
+		if message.is_empty() {
+			return Err(String::from("Your message is empty."));
+		}
+		if message.chars().count() > self.settings.max_message_length {
+			return Err(format!("Your message is too long, at most {} characters are allowed.", self.settings.max_message_length));
+		}
+		Ok(message.to_owned())
+	}
+	
+	//Message from the server to everyone in game, like join notices:
+	pub fn broadcast_system(network: &NetworkThread, sessions: &PlayerSessions, message: &str) {
+		log_info!("[Chat] ", message);
+		Chat::broadcast(network, sessions, &ChatMessageBroadcast {
+			sender: SYSTEM_SENDER,
+			message,
+			sender_color: SYSTEM_COLOR,
+		});
+	}
+	
+	//Message from the server to a single player, like why its message got refused:
+	pub fn send_system_to(network: &NetworkThread, address: SocketAddr, message: &str) {
+		let mut buffer = Vec::new();
+		ChatMessageBroadcast {
+			sender: SYSTEM_SENDER,
+			message,
+			sender_color: SYSTEM_COLOR,
+		}.write(&mut buffer);
+		network.send_with_priority(address, SendPriority::High, buffer);
+	}
+	
+	fn broadcast(network: &NetworkThread, sessions: &PlayerSessions, packet: &ChatMessageBroadcast) {
+		let mut buffer = Vec::new();
+		packet.write(&mut buffer);
+		for session in sessions.in_game() {
+			network.send_with_priority(session.address, SendPriority::High, buffer.clone());
+		}
+	}
+	
+	pub fn player_left(&mut self, address: &SocketAddr) {
+		self.last_message.remove(address);
+	}
+}
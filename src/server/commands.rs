@@ -0,0 +1,457 @@
+use crate::prelude::*;
+
+use std::net::SocketAddr;
+use std::str::FromStr;
+
+use crate::files::access_lists::IpRange;
+use crate::files::world_data::world_file_writer;
+use crate::lidgren::network_thread::NetworkThread;
+use crate::lidgren::send_priority::SendPriority;
+use crate::network::packets::s2c::debug_message::DebugMessage;
+use crate::server::chat::Chat;
+use crate::server::game_state::GameState;
+use crate::server::player_sessions::SessionState;
+use crate::util::error_handling::Stacktrace;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Permission {
+	Everyone,
+	//Players connecting from an admin IP of the access lists:
+	Admin,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ArgumentKind {
+	//A single word:
+	Word,
+	//Username of a player on the server:
+	Player,
+	Number,
+	//Everything that is left, spaces included. Only makes sense as last argument:
+	Text,
+}
+
+pub struct Argument {
+	pub name: &'static str,
+	pub kind: ArgumentKind,
+	pub required: bool,
+}
+
+pub enum ArgumentValue {
+	Word(String),
+	Player(SocketAddr),
+	Number(f64),
+	Text(String),
+}
+
+//Parsed arguments, in the order the command declared them. Optional arguments that were not given are missing:
+pub struct Arguments {
+	values: Vec<ArgumentValue>,
+}
+
+impl Arguments {
+	pub fn word(&self, index: usize) -> Option<&str> {
+		match self.values.get(index) {
+			Some(ArgumentValue::Word(value)) | Some(ArgumentValue::Text(value)) => Some(value),
+			_ => None,
+		}
+	}
+	
+	pub fn player(&self, index: usize) -> Option<SocketAddr> {
+		match self.values.get(index) {
+			Some(ArgumentValue::Player(address)) => Some(*address),
+			_ => None,
+		}
+	}
+	
+	pub fn number(&self, index: usize) -> Option<f64> {
+		match self.values.get(index) {
+			Some(ArgumentValue::Number(value)) => Some(*value),
+			_ => None,
+		}
+	}
+}
+
+pub struct CommandContext<'a> {
+	pub network: &'a NetworkThread,
+	pub commands: &'a CommandRegistry,
+	pub state: &'a mut GameState,
+	pub sender: SocketAddr,
+	pub is_admin: bool,
+	replies: Vec<String>,
+}
+
+impl<'a> CommandContext<'a> {
+	pub fn reply(&mut self, message: impl Into<String>) {
+		self.replies.push(message.into());
+	}
+}
+
+//Err contains the message to show to the sender:
+pub type CommandResult = Result<(), String>;
+
+pub struct Command {
+	pub name: &'static str,
+	pub aliases: &'static [&'static str],
+	pub description: &'static str,
+	pub permission: Permission,
+	pub arguments: &'static [Argument],
+	pub execute: fn(&mut CommandContext, &Arguments) -> CommandResult,
+}
+
+impl Command {
+	pub fn usage(&self) -> String {
+		let mut usage = String::from(self.name);
+		for argument in self.arguments {
+			if argument.required {
+				usage.push_str(&format!(" <{}>", argument.name));
+			} else {
+				usage.push_str(&format!(" [{}]", argument.name));
+			}
+		}
+		usage
+	}
+	
+	fn parse_arguments(&self, state: &GameState, mut remaining: &str) -> Result<Arguments, String> {
+		let mut values = Vec::with_capacity(self.arguments.len());
+		for argument in self.arguments {
+			remaining = remaining.trim_start();
+			if remaining.is_empty() {
+				if argument.required {
+					return Err(format!("Missing argument <{}>.", argument.name));
+				}
+				break;
+			}
+			let value = if let ArgumentKind::Text = argument.kind {
+				let text = remaining.trim_end();
+				remaining = "";
+				text
+			} else {
+				let (value, rest) = remaining.split_once(char::is_whitespace).unwrap_or((remaining, ""));
+				remaining = rest;
+				value
+			};
+			values.push(match argument.kind {
+				ArgumentKind::Word => ArgumentValue::Word(value.to_owned()),
+				ArgumentKind::Text => ArgumentValue::Text(value.to_owned()),
+				ArgumentKind::Player => match state.sessions.find_by_username(value) {
+					Some(session) => ArgumentValue::Player(session.address),
+					None => return Err(format!("There is no player named '{}' on the server.", value)),
+				},
+				ArgumentKind::Number => match f64::from_str(value) {
+					Ok(number) if number.is_finite() => ArgumentValue::Number(number),
+					_ => return Err(format!("Argument <{}> has to be a number, got '{}'.", argument.name, value)),
+				},
+			});
+		}
+		if !remaining.trim().is_empty() {
+			return Err(String::from("Too many arguments."));
+		}
+		Ok(Arguments {
+			values,
+		})
+	}
+}
+
+//Commands players can run from their console, replies are sent back to that console.
+pub struct CommandRegistry {
+	commands: Vec<Command>,
+}
+
+impl CommandRegistry {
+	pub fn new() -> Self {
+		Self {
+			commands: Vec::new(),
+		}
+	}
+	
+	pub fn with_defaults() -> Self {
+		let mut registry = CommandRegistry::new();
+		registry.register(HELP);
+		registry.register(LIST);
+		registry.register(KICK);
+		registry.register(BAN);
+		registry.register(UNBAN);
+		registry.register(BAN_IP);
+		registry.register(UNBAN_IP);
+		registry.register(SAVE);
+		registry.register(SIMULATION_SPEED);
+		registry
+	}
+	
+	pub fn register(&mut self, command: Command) {
+		let is_taken = |name: &str| self.get(name).is_some();
+		if is_taken(command.name) || command.aliases.iter().any(|alias| is_taken(alias)) {
+			log_error!("Command '", command.name, "' or one of its aliases is already registered, ignoring it.");
+			return;
+		}
+		self.commands.push(command);
+	}
+	
+	pub fn get(&self, name: &str) -> Option<&Command> {
+		self.commands.iter().find(|command| command.name == name || command.aliases.contains(&name))
+	}
+	
+	//Names of the commands starting with the prefix, suggested when an unknown command got run.
+	//The protocol has no packet to ask the server for completions, the client completes on its own:
+	pub fn suggestions(&self, prefix: &str) -> Vec<&'static str> {
+		let prefix = prefix.to_lowercase();
+		let mut names: Vec<&'static str> = self.commands.iter()
+			.flat_map(|command| std::iter::once(command.name).chain(command.aliases.iter().copied()))
+			.filter(|name| name.starts_with(&prefix))
+			.collect();
+		names.sort_unstable();
+		names
+	}
+	
+	pub fn execute(&self, network: &NetworkThread, state: &mut GameState, sender: SocketAddr, line: &str) {
+		let session = unwrap_or_return!(state.sessions.get_active(&sender), {
+			log_warn!("Ignoring command of ", sender.ip(), ":", sender.port(), ", there is no session for it.");
+		});
+		let username = session.username.clone();
+		let is_admin = state.access_lists.is_admin(&sender.ip());
+		log_info!("[Command] ", username, " ran: ", line);
+		
+		let line = line.trim();
+		let line = line.strip_prefix('/').unwrap_or(line);
+		let (name, arguments) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+		let name = name.to_lowercase();
+		
+		let mut context = CommandContext {
+			network,
+			commands: self,
+			state,
+			sender,
+			is_admin,
+			replies: Vec::new(),
+		};
+		let result = match self.get(&name) {
+			None => {
+				let suggestions = self.suggestions(&name);
+				if name.is_empty() || suggestions.is_empty() {
+					Err(format!("Unknown command '{}', run 'help' to see all commands.", name))
+				} else {
+					Err(format!("Unknown command '{}', did you mean: {}", name, suggestions.join(", ")))
+				}
+			}
+			Some(command) if command.permission == Permission::Admin && !is_admin => {
+				Err(String::from("You are not allowed to run this command."))
+			}
+			Some(command) => match command.parse_arguments(context.state, arguments) {
+				Ok(arguments) => (command.execute)(&mut context, &arguments),
+				Err(error) => Err(format!("{}\nUsage: {}", error, command.usage())),
+			},
+		};
+		if let Err(error) = result {
+			context.reply(error);
+		}
+		if context.replies.is_empty() {
+			return;
+		}
+		let mut buffer = Vec::new();
+		DebugMessage {
+			message: &context.replies.join("\n"),
+		}.write(&mut buffer);
+		network.send_with_priority(sender, SendPriority::High, buffer);
+	}
+}
+
+impl Default for CommandRegistry {
+	fn default() -> Self {
+		CommandRegistry::with_defaults()
+	}
+}
+
+//### Built-in commands: ###
+
+const HELP: Command = Command {
+	name: "help",
+	aliases: &["?"],
+	description: "Lists all commands, or shows how to use one.",
+	permission: Permission::Everyone,
+	arguments: &[Argument { name: "command", kind: ArgumentKind::Word, required: false }],
+	execute: |context, arguments| {
+		if let Some(name) = arguments.word(0) {
+			let command = context.commands.get(&name.to_lowercase()).ok_or_else(|| format!("Unknown command '{}'.", name))?;
+			let usage = command.usage();
+			context.reply(format!("{}\n  {}", usage, command.description));
+			return Ok(());
+		}
+		let lines: Vec<String> = context.commands.commands.iter()
+			.filter(|command| command.permission == Permission::Everyone || context.is_admin)
+			.map(|command| format!("{} - {}", command.usage(), command.description))
+			.collect();
+		context.reply(format!("Commands:\n{}", lines.join("\n")));
+		Ok(())
+	},
+};
+
+const LIST: Command = Command {
+	name: "list",
+	aliases: &["players"],
+	description: "Lists the players on the server.",
+	permission: Permission::Everyone,
+	arguments: &[],
+	execute: |context, _| {
+		let mut lines: Vec<String> = context.state.sessions.all()
+			.map(|session| format!("{} ({:?})", session.username, session.state))
+			.collect();
+		lines.sort_unstable();
+		context.reply(format!("{} player(s) on the server:\n{}", lines.len(), lines.join("\n")));
+		Ok(())
+	},
+};
+
+const KICK: Command = Command {
+	name: "kick",
+	aliases: &[],
+	description: "Disconnects a player.",
+	permission: Permission::Admin,
+	arguments: &[
+		Argument { name: "player", kind: ArgumentKind::Player, required: true },
+		Argument { name: "reason", kind: ArgumentKind::Text, required: false },
+	],
+	execute: |context, arguments| {
+		let address = arguments.player(0).unwrap();
+		let reason = arguments.word(1).unwrap_or("Kicked by an admin.");
+		let username = kick(context, address, reason).ok_or_else(|| String::from("That player is no longer on the server."))?;
+		context.reply(format!("Kicked {}.", username));
+		Ok(())
+	},
+};
+
+const BAN: Command = Command {
+	name: "ban",
+	aliases: &[],
+	description: "Bans a username, the player gets kicked if on the server.",
+	permission: Permission::Admin,
+	arguments: &[
+		Argument { name: "username", kind: ArgumentKind::Word, required: true },
+		Argument { name: "reason", kind: ArgumentKind::Text, required: false },
+	],
+	execute: |context, arguments| {
+		let username = arguments.word(0).unwrap();
+		if !context.state.access_lists.ban_user(username).map_err(save_failed)? {
+			return Err(format!("{} is already banned.", username));
+		}
+		let address = context.state.sessions.find_by_username(username).map(|session| session.address);
+		if let Some(address) = address {
+			kick(context, address, arguments.word(1).unwrap_or("You got banned from this server."));
+		}
+		context.reply(format!("Banned {}.", username));
+		Ok(())
+	},
+};
+
+const UNBAN: Command = Command {
+	name: "unban",
+	aliases: &["pardon"],
+	description: "Removes a username from the ban list.",
+	permission: Permission::Admin,
+	arguments: &[Argument { name: "username", kind: ArgumentKind::Word, required: true }],
+	execute: |context, arguments| {
+		let username = arguments.word(0).unwrap();
+		if !context.state.access_lists.unban_user(username).map_err(save_failed)? {
+			return Err(format!("{} is not banned.", username));
+		}
+		context.reply(format!("Unbanned {}.", username));
+		Ok(())
+	},
+};
+
+const BAN_IP: Command = Command {
+	name: "ban-ip",
+	aliases: &[],
+	description: "Bans an IP address or network (CIDR), players from it get kicked.",
+	permission: Permission::Admin,
+	arguments: &[Argument { name: "ip or cidr", kind: ArgumentKind::Word, required: true }],
+	execute: |context, arguments| {
+		let range = IpRange::from_str(arguments.word(0).unwrap())?;
+		if !context.state.access_lists.ban_ip(range.clone()).map_err(save_failed)? {
+			return Err(format!("{} is already banned.", range));
+		}
+		let addresses: Vec<SocketAddr> = context.state.sessions.all()
+			.map(|session| session.address)
+			.filter(|address| range.contains(&address.ip()))
+			.collect();
+		for address in addresses {
+			kick(context, address, "You got banned from this server.");
+		}
+		context.reply(format!("Banned {}.", range));
+		Ok(())
+	},
+};
+
+const UNBAN_IP: Command = Command {
+	name: "unban-ip",
+	aliases: &[],
+	description: "Removes an IP address or network (CIDR) from the ban list.",
+	permission: Permission::Admin,
+	arguments: &[Argument { name: "ip or cidr", kind: ArgumentKind::Word, required: true }],
+	execute: |context, arguments| {
+		let range = IpRange::from_str(arguments.word(0).unwrap())?;
+		if !context.state.access_lists.unban_ip(&range).map_err(save_failed)? {
+			return Err(format!("{} is not banned.", range));
+		}
+		context.reply(format!("Unbanned {}.", range));
+		Ok(())
+	},
+};
+
+const SAVE: Command = Command {
+	name: "save",
+	aliases: &[],
+	description: "Saves the world to disk.",
+	permission: Permission::Admin,
+	arguments: &[],
+	execute: |context, _| {
+		world_file_writer::save_world(&context.state.folders, &context.state.world).map_err(save_failed)?;
+		context.reply("World saved.");
+		Ok(())
+	},
+};
+
+const SIMULATION_SPEED: Command = Command {
+	name: "tps",
+	aliases: &["simulationspeed"],
+	description: "Sets the simulation speed in ticks per second.",
+	permission: Permission::Admin,
+	arguments: &[Argument { name: "ticks per second", kind: ArgumentKind::Number, required: true }],
+	execute: |context, arguments| {
+		let speed = arguments.number(0).unwrap();
+		if speed < 0.0 {
+			return Err(String::from("The simulation speed cannot be negative."));
+		}
+		let packet = context.state.extra_data.set_simulation_speed(speed)
+			.ok_or_else(|| String::from("The simulation speed could not be changed."))?;
+		for session in context.state.sessions.all() {
+			context.network.send_to(session.address, packet.clone());
+		}
+		context.reply(format!("Simulation speed set to {} TPS.", speed));
+		Ok(())
+	},
+};
+
+//Returns the username of the kicked player, if there was a player session for the address:
+fn kick(context: &mut CommandContext, address: SocketAddr, reason: &str) -> Option<String> {
+	let (username, state) = unwrap_or_return!(context.state.sessions.get(&address).map(|session| (session.username.clone(), session.state)), None);
+	if state == SessionState::Connecting {
+		//No connection to drop yet, the handshake gets refused. There will be no lifecycle event to end the session:
+		context.network.reject_connect(&address, reason);
+		context.state.sessions.end(&address);
+		context.state.chat.player_left(&address);
+	} else {
+		context.state.sessions.leave(&address);
+		context.network.disconnect(address, reason);
+	}
+	//Only players that joined the game got announced:
+	if state == SessionState::InGame {
+		Chat::broadcast_system(context.network, &context.state.sessions, &format!("{} got kicked.", username));
+	}
+	Some(username)
+}
+
+fn save_failed(error: Stacktrace) -> String {
+	error.print();
+	String::from("Saving failed, see the server log for details.")
+}
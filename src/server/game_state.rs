@@ -0,0 +1,18 @@
+use crate::files::access_lists::AccessLists;
+use crate::files::extra_data::manager::ExtraDataManager;
+use crate::files::world_data::world_structs::World;
+use crate::files::world_files::WorldFolderAccess;
+use crate::server::build_validation::BuildLimits;
+use crate::server::chat::Chat;
+use crate::server::player_sessions::PlayerSessions;
+
+//Everything the game loop works on, so that packet handlers and commands can reach all of it:
+pub struct GameState {
+	pub folders: WorldFolderAccess,
+	pub world: World,
+	pub extra_data: ExtraDataManager,
+	pub access_lists: AccessLists,
+	pub sessions: PlayerSessions,
+	pub chat: Chat,
+	pub build_limits: BuildLimits,
+}
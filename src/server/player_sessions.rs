@@ -0,0 +1,122 @@
+use crate::prelude::*;
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::Instant;
+
+use crate::network::packets::c2s::connection_approval::ConnectionApproval;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SessionState {
+	//Connect got approved, the Lidgren connection is not yet established:
+	Connecting,
+	//The world got sent, the client is still processing it:
+	LoadingWorld,
+	//The client loaded the world and may interact with it:
+	InGame,
+	//The server is disconnecting the client, whatever it still sends is ignored:
+	Leaving,
+}
+
+pub struct PlayerSession {
+	pub address: SocketAddr,
+	pub username: String,
+	pub mods: Vec<String>,
+	pub client_version: String,
+	pub password_hash: Option<Vec<u8>>,
+	pub state: SessionState,
+	pub connected_since: Instant,
+	//World updates that happened after the world got sent, held back until the client loaded it:
+	pub pending_world_updates: Vec<Vec<u8>>,
+}
+
+//Everyone who is connected or about to be, by connection.
+//Sessions start with an approved connect and end with the connection lifecycle event of the network.
+#[derive(Default)]
+pub struct PlayerSessions {
+	sessions: HashMap<SocketAddr, PlayerSession>,
+}
+
+impl PlayerSessions {
+	//Returns the reason to show the client, if it may not join:
+	pub fn check_username(&self, username: &str) -> Option<String> {
+		if username.trim().is_empty() {
+			return Some(String::from("Your username is empty."));
+		}
+		let is_taken = self.sessions.values()
+			.any(|session| session.state != SessionState::Leaving && session.username.eq_ignore_ascii_case(username));
+		if is_taken {
+			return Some(String::from("A player with your username is already on this server."));
+		}
+		None
+	}
+	
+	pub fn begin(&mut self, address: SocketAddr, approval: ConnectionApproval) {
+		log_info!("Player '", approval.username, "' is connecting from ", address.ip(), ":", address.port(), " with client version ", approval.version);
+		self.sessions.insert(address, PlayerSession {
+			address,
+			username: approval.username,
+			mods: approval.mods,
+			client_version: approval.version,
+			password_hash: approval.password_hash,
+			state: SessionState::Connecting,
+			connected_since: Instant::now(),
+			pending_world_updates: Vec::new(),
+		});
+	}
+	
+	//Moves the session from one state to the next, returns false if it was not in the expected state:
+	pub fn advance(&mut self, address: &SocketAddr, from: SessionState, to: SessionState) -> bool {
+		let session = unwrap_or_return!(self.sessions.get_mut(address), false);
+		if session.state != from {
+			log_warn!("Player '", session.username, "' is ", format!("{:?}", session.state), ", but it is expected to be ", format!("{:?}", from), " before it can be ", format!("{:?}", to));
+			return false;
+		}
+		log_debug!("Player '", session.username, "' is now ", format!("{:?}", to));
+		session.state = to;
+		true
+	}
+	
+	//The server decided to drop the player, the network will confirm it with a lifecycle event:
+	pub fn leave(&mut self, address: &SocketAddr) {
+		if let Some(session) = self.sessions.get_mut(address) {
+			session.state = SessionState::Leaving;
+		}
+	}
+	
+	//The connection is gone, returns the session (in its last state) if there was one:
+	pub fn end(&mut self, address: &SocketAddr) -> Option<PlayerSession> {
+		self.sessions.remove(address)
+	}
+	
+	//Returns the world updates held back while the player was loading the world:
+	pub fn take_pending_world_updates(&mut self, address: &SocketAddr) -> Vec<Vec<u8>> {
+		self.sessions.get_mut(address).map(|session| std::mem::take(&mut session.pending_world_updates)).unwrap_or_default()
+	}
+	
+	pub fn get(&self, address: &SocketAddr) -> Option<&PlayerSession> {
+		self.sessions.get(address)
+	}
+	
+	//Sessions which are not leaving, packets from other remotes are to be ignored:
+	pub fn get_active(&self, address: &SocketAddr) -> Option<&PlayerSession> {
+		self.sessions.get(address).filter(|session| session.state != SessionState::Leaving)
+	}
+	
+	pub fn find_by_username(&self, username: &str) -> Option<&PlayerSession> {
+		self.sessions.values().find(|session| session.username.eq_ignore_ascii_case(username))
+	}
+	
+	//Players that loaded the world, these get all world and chat updates:
+	pub fn in_game(&self) -> impl Iterator<Item = &PlayerSession> {
+		self.sessions.values().filter(|session| session.state == SessionState::InGame)
+	}
+	
+	pub fn all(&self) -> impl Iterator<Item = &PlayerSession> {
+		self.sessions.values()
+	}
+	
+	pub fn all_mut(&mut self) -> impl Iterator<Item = &mut PlayerSession> {
+		self.sessions.values_mut()
+	}
+}
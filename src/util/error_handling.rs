@@ -269,5 +269,14 @@ macro_rules! _unwrap_or_print_return {
 			}
 		}
 	};
+	($val:expr, $ret:expr) => {
+		match $val {
+			Ok(x) => x,
+			Err(message) => {
+				$crate::util::error_handling::Stacktrace::print(&message);
+				return $ret;
+			}
+		}
+	};
 }
 pub use _unwrap_or_print_return as unwrap_or_print_return;